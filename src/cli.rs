@@ -2,9 +2,8 @@ use clap::{Parser, Subcommand};
 use anyhow::Result;
 use colored::*;
 use dialoguer::{Select, theme::ColorfulTheme};
-use std::io::{self, Write};
 
-use crate::{config::Config, session::Session, llm::LlmProvider};
+use crate::{config::{Config, ReasoningEffort, SamplingParams}, interrupt, session::Session, llm::LlmProvider, models, pricing::UsageReport};
 
 #[derive(Parser)]
 #[command(name = "crush")]
@@ -17,11 +16,32 @@ pub struct Cli {
     #[arg(long, help = "Skip all permission prompts (use with extreme care)")]
     pub yolo: bool,
     
-    #[arg(long, help = "Specify the LLM provider to use")]
+    #[arg(long, global = true, help = "Specify the LLM provider to use")]
     pub provider: Option<String>,
     
     #[arg(long, help = "Session name to use or create")]
     pub session: Option<String>,
+    
+    #[arg(long, global = true, help = "Sampling temperature for this run")]
+    pub temperature: Option<f64>,
+    
+    #[arg(long, global = true, help = "Nucleus sampling probability mass for this run")]
+    pub top_p: Option<f64>,
+    
+    #[arg(long, global = true, help = "Maximum number of tokens to generate per response")]
+    pub max_tokens: Option<u32>,
+    
+    #[arg(long = "stop", global = true, help = "Stop sequence (can be repeated)")]
+    pub stop: Vec<String>,
+    
+    #[arg(long, global = true, help = "Seed for deterministic sampling, where supported")]
+    pub seed: Option<u64>,
+    
+    #[arg(long, global = true, value_enum, help = "Reasoning effort for reasoning models")]
+    pub reasoning_effort: Option<ReasoningEffort>,
+    
+    #[arg(long, global = true, help = "Tokens the model may spend thinking, where supported")]
+    pub thinking_budget: Option<u32>,
 }
 
 #[derive(Subcommand)]
@@ -37,11 +57,25 @@ pub enum Commands {
     Config,
     #[command(about = "Show current configuration")]
     Status,
+    #[command(about = "List the models each provider offers")]
+    Models {
+        #[arg(long, help = "Fetch the lists again instead of using the cache")]
+        refresh: bool,
+    },
 }
 
 impl Cli {
     pub async fn run(&self) -> Result<()> {
         let mut config = Config::load_or_create().await?;
+        config.parameter_overrides = SamplingParams {
+            temperature: self.temperature,
+            top_p: self.top_p,
+            max_tokens: self.max_tokens,
+            stop: self.stop.clone(),
+            seed: self.seed,
+            reasoning_effort: self.reasoning_effort,
+            thinking_budget: self.thinking_budget,
+        };
         
         match &self.command {
             Some(Commands::Chat { message }) => {
@@ -56,6 +90,9 @@ impl Cli {
             Some(Commands::Status) => {
                 self.show_status(&config).await?;
             }
+            Some(Commands::Models { refresh }) => {
+                self.list_models(&config, *refresh).await?;
+            }
             None => {
                 // If no provider specified and no default provider, show model selection
                 if self.provider.is_none() && config.default_provider.is_none() {
@@ -72,12 +109,15 @@ impl Cli {
     async fn start_chat_session(&self, config: &mut Config, initial_message: Option<String>) -> Result<()> {
         println!("{}", "🌟 Welcome to Crush - Your AI Coding Assistant".bright_magenta().bold());
         
+        interrupt::install();
         let provider = self.get_provider(config).await?;
+        let fallbacks = self.get_fallback_providers(config, &provider.name).await;
         let mut session = Session::new_or_load(
             self.session.clone(),
             provider,
+            &config.global_settings,
             self.yolo
-        ).await?;
+        ).await?.with_fallbacks(fallbacks);
         
         if let Some(msg) = initial_message {
             session.send_message(&msg).await?;
@@ -112,6 +152,76 @@ impl Cli {
         println!("{}", "Crush Status".bright_cyan().bold());
         println!("Provider: {}", config.default_provider.as_deref().unwrap_or("None").bright_white());
         println!("Config path: {}", config.config_path().display().to_string().dimmed());
+        
+        let mut total = UsageReport::default();
+        let mut by_session = Vec::new();
+        for name in Session::list_all().await? {
+            match Session::load_usage(&name).await {
+                Ok(report) if report.total.requests > 0 => {
+                    total.merge(&report);
+                    by_session.push((name, report.total));
+                }
+                Ok(_) => {}
+                Err(e) => println!("{}", format!("⚠️  Could not read session '{}': {}", name, e).bright_yellow()),
+            }
+        }
+        
+        if by_session.is_empty() {
+            println!("Usage: {}", "no requests recorded yet".dimmed());
+            return Ok(());
+        }
+        
+        println!("\n{}", "Usage".bright_cyan().bold());
+        total.print("  ");
+        println!("  By session:");
+        for (name, totals) in &by_session {
+            println!("    {}: {}", name.bright_white(), totals.summary());
+        }
+        Ok(())
+    }
+    
+    // Models of the provider given with --provider, or of every configured provider
+    async fn list_models(&self, config: &Config, refresh: bool) -> Result<()> {
+        let names = match &self.provider {
+            Some(name) => vec![name.clone()],
+            None => {
+                let mut names: Vec<String> = config.providers.keys().cloned().collect();
+                names.sort();
+                names
+            }
+        };
+        
+        for name in &names {
+            println!("{}", name.bright_cyan().bold());
+            
+            let models = match LlmProvider::new(name, config).await {
+                Ok(provider) => models::list(&provider, refresh).await
+                    .map(|models| (models, provider.config.model.clone())),
+                Err(e) => Err(e),
+            };
+            let (models, current) = match models {
+                Ok(found) => found,
+                // Asked about one provider: its failure is the command's failure
+                Err(e) if self.provider.is_some() => return Err(e),
+                Err(e) => {
+                    println!("  {}\n", format!("⚠️  {}", e.to_string().lines().next().unwrap_or_default()).bright_yellow());
+                    continue;
+                }
+            };
+            
+            if models.is_empty() {
+                println!("  {}", "No models listed".dimmed());
+            }
+            for model in &models {
+                if Some(model) == current.as_ref() {
+                    println!("  • {} {}", model.bright_white().bold(), "(current)".green());
+                } else {
+                    println!("  • {}", model);
+                }
+            }
+            println!();
+        }
+        
         Ok(())
     }
     
@@ -123,6 +233,25 @@ impl Cli {
         LlmProvider::new(provider_name, config).await
     }
     
+    // The configured fallback chain, minus the provider already in use. Providers that
+    // cannot be set up are reported and left out rather than failing the whole session.
+    async fn get_fallback_providers(&self, config: &Config, primary: &str) -> Vec<LlmProvider> {
+        let mut fallbacks = Vec::new();
+        
+        for name in config.fallback_providers.iter().filter(|name| name.as_str() != primary) {
+            match LlmProvider::new(name, &config.for_fallback(name)).await {
+                Ok(provider) => fallbacks.push(provider),
+                Err(e) => println!("{}", format!(
+                    "⚠️  Skipping fallback provider '{}': {}",
+                    name,
+                    e.to_string().lines().next().unwrap_or_default()
+                ).bright_yellow()),
+            }
+        }
+        
+        fallbacks
+    }
+    
     async fn show_provider_selection_and_chat(&self, config: &mut Config) -> Result<()> {
         println!("{}", "🌟 Welcome to Crush - Your AI Coding Assistant".bright_magenta().bold());
         println!();
@@ -144,6 +273,7 @@ impl Cli {
             let description = match name.as_str() {
                 "openai" => format!("OpenAI GPT-4 - Industry leading AI model {}", status),
                 "anthropic" => format!("Anthropic Claude - Advanced reasoning capabilities {}", status),
+                "gemini" => format!("Google Gemini - Long context, multimodal {}", status),
                 "deepseek" => format!("DeepSeek - High performance, cost-effective {}", status),
                 "ollama" => format!("Ollama - Local AI models (llama3.2) {}", status),
                 "kimi2" => format!("Kimi - Moonshot AI with excellent Chinese support {}", status),
@@ -186,11 +316,14 @@ impl Cli {
         // Create provider and start chat
         match LlmProvider::new(selected_provider, config).await {
             Ok(provider) => {
+                interrupt::install();
+                let fallbacks = self.get_fallback_providers(config, selected_provider).await;
                 let mut session = Session::new_or_load(
                     self.session.clone(),
                     provider,
+                    &config.global_settings,
                     self.yolo
-                ).await?;
+                ).await?.with_fallbacks(fallbacks);
                 
                 session.start_interactive_loop().await?;
             }
@@ -249,6 +382,12 @@ impl Cli {
                 println!("3. Set environment variable:");
                 println!("   {}", "set ANTHROPIC_API_KEY=your-key-here".bright_white());
             }
+            "gemini" => {
+                println!("1. Visit https://aistudio.google.com/apikey");
+                println!("2. Create an API key");
+                println!("3. Set environment variable:");
+                println!("   {}", "set GEMINI_API_KEY=your-key-here".bright_white());
+            }
             "deepseek" => {
                 println!("1. Visit https://platform.deepseek.com/");
                 println!("2. Create an API key");
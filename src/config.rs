@@ -4,26 +4,141 @@ use std::collections::HashMap;
 use std::path::PathBuf;
 use tokio::fs;
 
+use crate::pricing::ModelPrice;
+use crate::providers::{MockStep, OpenAiProvider};
+use crate::retry::RetrySettings;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub default_provider: Option<String>,
+    // Providers tried in order when the selected one is rate limited or unavailable
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fallback_providers: Vec<String>,
     pub providers: HashMap<String, ProviderConfig>,
     pub global_settings: GlobalSettings,
+    
+    // Sampling parameters given on the command line, applied on top of everything else
+    #[serde(skip)]
+    pub parameter_overrides: SamplingParams,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ProviderConfig {
     pub api_type: ApiType,
     pub api_key: Option<String>,
     pub base_url: Option<String>,
     pub model: Option<String>,
+    // Registered protocol implementation to use when api_type is Custom
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<String>,
+    // Azure OpenAI deployment name; requests go to /openai/deployments/{deployment}/...
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deployment: Option<String>,
+    // api-version query parameter sent with deployment requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_version: Option<String>,
+    // How the API key is sent; Bearer by default, ApiKey for deployments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<AuthStyle>,
+    #[serde(default, skip_serializing_if = "SamplingParams::is_empty")]
+    pub parameters: SamplingParams,
+    // Per-model parameters, keyed by model id, applied on top of `parameters`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub model_parameters: HashMap<String, SamplingParams>,
+    // Overrides global_settings.retry for this provider
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetrySettings>,
+    // Extra headers sent with every request, e.g. for a corporate gateway
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub headers: HashMap<String, String>,
+    // Proxy URL for all requests; HTTP_PROXY and HTTPS_PROXY are used when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    // PEM file with extra root certificates to trust
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_cert_path: Option<PathBuf>,
+    // In seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connect_timeout: Option<u64>,
+    // In seconds, for the whole request including a streamed response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_timeout: Option<u64>,
+    // Context window of the model in tokens, for models Crush does not know about
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_window: Option<u32>,
+    // Prices per model id, overriding the built-in list prices
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub pricing: HashMap<String, ModelPrice>,
+    // Anthropic prompt caching of the system prompt, tools and earlier turns; on unless false
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_caching: Option<bool>,
+    // Replies returned in order when api_type is Mock
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub script: Vec<MockStep>,
+    // Records requests and responses to a file, or answers from one without a network
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cassette: Option<CassetteSettings>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CassetteSettings {
+    pub path: PathBuf,
+    pub mode: CassetteMode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CassetteMode {
+    Record,
+    Replay,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuthStyle {
+    // Authorization: Bearer <key>
+    Bearer,
+    // api-key: <key>, as Azure OpenAI expects
+    ApiKey,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SamplingParams {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_effort: Option<ReasoningEffort>,
+    // Tokens the model may spend thinking before it answers, for Anthropic and Gemini
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thinking_budget: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ReasoningEffort {
+    Minimal,
+    Low,
+    Medium,
+    High,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub enum ApiType {
+    #[default]
     OpenAI,
     Anthropic,
+    Gemini,
     Custom,
+    // Scripted replies from `script`, for tests and demos
+    Mock,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,6 +146,34 @@ pub struct GlobalSettings {
     pub auto_approve_safe_tools: bool,
     pub max_tokens: Option<u32>,
     pub temperature: Option<f32>,
+    #[serde(default = "default_max_tool_steps")]
+    pub max_tool_steps: u32,
+    #[serde(default = "default_stream")]
+    pub stream: bool,
+    #[serde(default)]
+    pub retry: RetrySettings,
+    // Summarize older messages when the conversation gets close to the context window
+    #[serde(default = "default_auto_compact")]
+    pub auto_compact: bool,
+    // Print the model's thinking; when off it is collapsed into a one-line note
+    #[serde(default = "default_show_thinking")]
+    pub show_thinking: bool,
+}
+
+fn default_max_tool_steps() -> u32 {
+    25
+}
+
+fn default_stream() -> bool {
+    true
+}
+
+fn default_auto_compact() -> bool {
+    true
+}
+
+fn default_show_thinking() -> bool {
+    true
 }
 
 impl Default for Config {
@@ -42,6 +185,7 @@ impl Default for Config {
             api_key: std::env::var("OPENAI_API_KEY").ok(),
             base_url: Some("https://api.openai.com/v1".to_string()),
             model: Some("gpt-4".to_string()),
+            ..Default::default()
         });
         
         providers.insert("anthropic".to_string(), ProviderConfig {
@@ -49,6 +193,15 @@ impl Default for Config {
             api_key: std::env::var("ANTHROPIC_API_KEY").ok(),
             base_url: Some("https://api.anthropic.com/v1".to_string()),
             model: Some("claude-3-sonnet-20240229".to_string()),
+            ..Default::default()
+        });
+        
+        providers.insert("gemini".to_string(), ProviderConfig {
+            api_type: ApiType::Gemini,
+            api_key: std::env::var("GEMINI_API_KEY").ok(),
+            base_url: Some("https://generativelanguage.googleapis.com/v1beta".to_string()),
+            model: Some("gemini-2.5-flash".to_string()),
+            ..Default::default()
         });
         
         providers.insert("deepseek".to_string(), ProviderConfig {
@@ -56,6 +209,7 @@ impl Default for Config {
             api_key: std::env::var("DEEPSEEK_API_KEY").ok(),
             base_url: Some("https://api.deepseek.com/v1".to_string()),
             model: Some("deepseek-chat".to_string()),
+            ..Default::default()
         });
         
         providers.insert("ollama".to_string(), ProviderConfig {
@@ -63,6 +217,9 @@ impl Default for Config {
             api_key: None, // Ollama typically doesn't require API key for local usage
             base_url: Some("http://localhost:11434/v1".to_string()),
             model: Some("llama3.2".to_string()), // Default model, can be changed
+            // Local models cost nothing to run
+            pricing: [("llama3.2".to_string(), ModelPrice { input: 0.0, output: 0.0, cached_input: None, cache_write: None })].into(),
+            ..Default::default()
         });
         
         providers.insert("kimi2".to_string(), ProviderConfig {
@@ -70,20 +227,59 @@ impl Default for Config {
             api_key: std::env::var("KIMI_API_KEY").ok(),
             base_url: Some("https://api.moonshot.cn/v1".to_string()),
             model: Some("moonshot-v1-8k".to_string()),
+            ..Default::default()
         });
         
         Self {
             default_provider: None,
+            fallback_providers: Vec::new(),
+            parameter_overrides: SamplingParams::default(),
             providers,
             global_settings: GlobalSettings {
                 auto_approve_safe_tools: false,
                 max_tokens: Some(4000),
                 temperature: Some(0.7),
+                max_tool_steps: default_max_tool_steps(),
+                stream: default_stream(),
+                retry: RetrySettings::default(),
+                auto_compact: default_auto_compact(),
+                show_thinking: default_show_thinking(),
             },
         }
     }
 }
 
+impl SamplingParams {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+    
+    // Values set on `overrides` win over the ones already set here
+    pub fn overlay(mut self, overrides: &SamplingParams) -> Self {
+        self.temperature = overrides.temperature.or(self.temperature);
+        self.top_p = overrides.top_p.or(self.top_p);
+        self.max_tokens = overrides.max_tokens.or(self.max_tokens);
+        if !overrides.stop.is_empty() {
+            self.stop = overrides.stop.clone();
+        }
+        self.seed = overrides.seed.or(self.seed);
+        self.reasoning_effort = overrides.reasoning_effort.or(self.reasoning_effort);
+        self.thinking_budget = overrides.thinking_budget.or(self.thinking_budget);
+        self
+    }
+}
+
+impl ReasoningEffort {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReasoningEffort::Minimal => "minimal",
+            ReasoningEffort::Low => "low",
+            ReasoningEffort::Medium => "medium",
+            ReasoningEffort::High => "high",
+        }
+    }
+}
+
 impl Config {
     pub async fn load_or_create() -> Result<Self> {
         let config_path = Self::config_path_static();
@@ -126,4 +322,45 @@ impl Config {
     pub fn get_provider(&self, name: &str) -> Option<&ProviderConfig> {
         self.providers.get(name)
     }
+    
+    // Global defaults, then the provider's parameters, then the ones for its model,
+    // then whatever was passed on the command line
+    pub fn sampling_params(&self, provider: &ProviderConfig) -> SamplingParams {
+        let mut params = provider.parameters.clone();
+        if let Some(model_params) = provider.model.as_ref().and_then(|m| provider.model_parameters.get(m)) {
+            params = params.overlay(model_params);
+        }
+        params = params.overlay(&self.parameter_overrides);
+        
+        // The global defaults are meant for plain answers. With a thinking budget the provider
+        // picks its own max_tokens to leave room for the thinking, and neither Anthropic with a
+        // budget nor OpenAI's reasoning models allow a temperature, so only explicitly
+        // configured values are sent.
+        let reasoning_model = provider.model.as_deref().is_some_and(OpenAiProvider::is_reasoning_model);
+        if params.thinking_budget.is_some() || params.reasoning_effort.is_some() || reasoning_model {
+            return params;
+        }
+        
+        let defaults = SamplingParams {
+            // Go through the shortest decimal form so 0.7 stays 0.7 instead of 0.699999988
+            temperature: self.global_settings.temperature
+                .map(|t| t.to_string().parse().unwrap_or(f64::from(t))),
+            max_tokens: self.global_settings.max_tokens,
+            ..Default::default()
+        };
+        defaults.overlay(&params)
+    }
+    
+    // The configuration a fallback provider is created from. The command line's thinking_budget
+    // and reasoning_effort are meant for the selected provider, so they are left out for a
+    // fallback whose API only understands the other one, instead of ruling it out.
+    pub fn for_fallback(&self, name: &str) -> Config {
+        let mut config = self.clone();
+        match self.providers.get(name).map(|provider| &provider.api_type) {
+            Some(ApiType::OpenAI) => config.parameter_overrides.thinking_budget = None,
+            Some(ApiType::Anthropic | ApiType::Gemini) => config.parameter_overrides.reasoning_effort = None,
+            _ => {}
+        }
+        config
+    }
 }
\ No newline at end of file
@@ -0,0 +1,249 @@
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+use crate::message::{ChatMessage, ContentPart, Role};
+use crate::tools::ToolDefinition;
+
+// Used for models missing from the table below; set `context_window` on the provider to override
+pub const DEFAULT_CONTEXT_WINDOW: u32 = 32_768;
+
+// Room left for the reply when the provider does not set max_tokens
+const DEFAULT_OUTPUT_RESERVE: u32 = 4096;
+
+// Compaction starts once the prompt fills this share of the space left after the reply...
+const COMPACT_AT_PERCENT: usize = 80;
+// ...and keeps recent messages verbatim up to this share
+const KEEP_RECENT_PERCENT: usize = 30;
+
+// Tool results longer than this are cut when shown to the summarizer
+const SUMMARY_RESULT_CHARS: usize = 2000;
+
+// Context window sizes by model id prefix. More specific prefixes come first.
+const CONTEXT_WINDOWS: &[(&str, u32)] = &[
+    ("gpt-4.1", 1_047_576),
+    ("gpt-4o", 128_000),
+    ("gpt-4-turbo", 128_000),
+    ("gpt-4-32k", 32_768),
+    ("gpt-4", 8_192),
+    ("gpt-3.5-turbo", 16_385),
+    ("gpt-5", 400_000),
+    ("o1-mini", 128_000),
+    ("o1", 200_000),
+    ("o3", 200_000),
+    ("o4", 200_000),
+    ("claude-", 200_000),
+    ("deepseek-", 65_536),
+    ("moonshot-v1-8k", 8_192),
+    ("moonshot-v1-32k", 32_768),
+    ("moonshot-v1-128k", 131_072),
+    ("kimi-", 131_072),
+    ("gemini-", 1_048_576),
+    ("llama3", 131_072),
+    ("qwen", 32_768),
+];
+
+// Record of a compaction, kept in the session file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Compaction {
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub provider: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    pub tokens_before: usize,
+    pub tokens_after: usize,
+    // The messages the summary replaced. They are kept for reference but never sent again.
+    pub messages: Vec<ChatMessage>,
+}
+
+pub fn context_window(model: &str) -> Option<u32> {
+    let model = model.to_lowercase();
+    // Ollama tags look like "llama3.2:latest"; provider prefixes like "openai/gpt-4o" are dropped
+    let model = model.rsplit('/').next().unwrap_or(&model);
+    CONTEXT_WINDOWS.iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .map(|(_, window)| *window)
+}
+
+// Rough token count: about four bytes per token for text, a flat cost for images
+pub fn estimate_tokens(messages: &[ChatMessage], tools: &[ToolDefinition]) -> usize {
+    let tools: usize = tools.iter()
+        .map(|tool| text_tokens(&tool.name) + text_tokens(&tool.description) + text_tokens(&tool.parameters.to_string()))
+        .sum();
+    tools + messages.iter().map(message_tokens).sum::<usize>()
+}
+
+fn message_tokens(message: &ChatMessage) -> usize {
+    // Role markers and message framing
+    let overhead = 4;
+    overhead + message.content.iter()
+        .map(|part| match part {
+            ContentPart::Text { text } | ContentPart::Thinking { text, .. } => text_tokens(text),
+            ContentPart::ToolCall(call) => {
+                text_tokens(&call.name) + text_tokens(&serde_json::to_string(&call.arguments).unwrap_or_default())
+            }
+            ContentPart::ToolResult { result, .. } => text_tokens(&result.to_message_content()),
+            ContentPart::Image { .. } => 1600,
+        })
+        .sum::<usize>()
+}
+
+fn text_tokens(text: &str) -> usize {
+    text.len().div_ceil(4)
+}
+
+// Largest prompt, in estimated tokens, that leaves room for the reply
+pub fn prompt_budget(context_window: u32, max_tokens: Option<u32>) -> usize {
+    let reserve = max_tokens.unwrap_or(DEFAULT_OUTPUT_RESERVE).min(context_window / 2);
+    (context_window - reserve) as usize
+}
+
+pub fn needs_compaction(estimated_tokens: usize, budget: usize) -> bool {
+    estimated_tokens * 100 > budget * COMPACT_AT_PERCENT
+}
+
+// The messages to replace with a summary: everything after the system prompt up to the
+// recent messages that are kept verbatim. Returns None when there is too little to summarize.
+pub fn compaction_range(messages: &[ChatMessage], budget: usize) -> Option<Range<usize>> {
+    let start = match messages.first() {
+        Some(first) if first.role == Role::System => 1,
+        _ => 0,
+    };
+    let keep_budget = budget * KEEP_RECENT_PERCENT / 100;
+
+    // Tool results must stay right after the assistant message that called them, so a
+    // split never lands on a tool message
+    let candidates = (start + 2..messages.len()).filter(|&i| messages[i].role != Role::Tool);
+
+    let mut split = None;
+    let mut kept = messages[start..].iter().map(message_tokens).sum::<usize>();
+    let mut next = start;
+    for i in candidates {
+        kept -= messages[next..i].iter().map(message_tokens).sum::<usize>();
+        next = i;
+        split = Some(i);
+        if kept <= keep_budget {
+            break;
+        }
+    }
+
+    split.map(|split| start..split)
+}
+
+// The request sent to the provider to summarize the messages in a compaction range
+pub fn summary_request(messages: &[ChatMessage]) -> Vec<ChatMessage> {
+    let transcript = messages.iter()
+        .map(render_message)
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    vec![
+        ChatMessage::system(
+            "You summarize conversations between a user and Crush, an AI coding assistant, so the \
+            conversation can continue without the full history. Keep the user's goals and \
+            requirements, decisions made, files and commands involved, important tool output, \
+            and any work still in progress. Be concise and factual; write the summary only."
+        ),
+        ChatMessage::user(format!("Summarize this conversation:\n\n{}", transcript)),
+    ]
+}
+
+pub fn summary_message(summary: &str) -> ChatMessage {
+    ChatMessage::user(format!("[Summary of the earlier conversation]\n{}", summary.trim()))
+}
+
+fn render_message(message: &ChatMessage) -> String {
+    message.content.iter()
+        .filter_map(|part| match part {
+            ContentPart::Text { text } => Some(match message.role {
+                Role::Assistant => format!("Assistant: {}", text),
+                Role::System => format!("System: {}", text),
+                _ => format!("User: {}", text),
+            }),
+            ContentPart::ToolCall(call) => Some(format!(
+                "Assistant called tool {} with {}",
+                call.name,
+                serde_json::to_string(&call.arguments).unwrap_or_default()
+            )),
+            ContentPart::ToolResult { name, result, .. } => {
+                let output = result.to_message_content();
+                let output = if output.chars().count() > SUMMARY_RESULT_CHARS {
+                    format!("{}... (truncated)", output.chars().take(SUMMARY_RESULT_CHARS).collect::<String>())
+                } else {
+                    output
+                };
+                Some(format!("Tool {} returned: {}", name, output))
+            }
+            ContentPart::Image { .. } => Some("User: [image]".to_string()),
+            ContentPart::Thinking { .. } => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::{ToolCall, ToolResult};
+
+    fn long_text() -> String {
+        "word ".repeat(400)
+    }
+
+    fn tool_round(id: &str) -> Vec<ChatMessage> {
+        let call = ToolCall {
+            id: id.to_string(),
+            name: "read_file".to_string(),
+            arguments: Default::default(),
+            description: None,
+        };
+        let result = ToolResult {
+            success: true,
+            output: long_text(),
+            error: None,
+        };
+        vec![
+            ChatMessage::assistant(vec![ContentPart::ToolCall(call.clone())]),
+            ChatMessage::tool_results(vec![(call, result)]),
+        ]
+    }
+
+    #[test]
+    fn compaction_keeps_system_prompt_and_recent_messages() {
+        let mut messages = vec![ChatMessage::system("system")];
+        for _ in 0..6 {
+            messages.push(ChatMessage::user(long_text()));
+            messages.push(ChatMessage::assistant(vec![ContentPart::text(long_text())]));
+        }
+
+        let range = compaction_range(&messages, 3000).unwrap();
+        assert_eq!(range.start, 1);
+        assert!(range.end < messages.len());
+        let kept: usize = messages[range.end..].iter().map(message_tokens).sum();
+        assert!(kept <= 3000 * KEEP_RECENT_PERCENT / 100);
+    }
+
+    #[test]
+    fn compaction_never_separates_tool_calls_from_results() {
+        let mut messages = vec![ChatMessage::system("system"), ChatMessage::user("go")];
+        for i in 0..5 {
+            messages.extend(tool_round(&i.to_string()));
+        }
+
+        for budget in [100, 1000, 2000, 5000] {
+            if let Some(range) = compaction_range(&messages, budget) {
+                assert_ne!(messages[range.end].role, Role::Tool);
+            }
+        }
+        assert_eq!(compaction_range(&messages[..3], 100), None);
+    }
+
+    #[test]
+    fn looks_up_context_windows_by_prefix() {
+        assert_eq!(context_window("gpt-4o-mini"), Some(128_000));
+        assert_eq!(context_window("gpt-4-0613"), Some(8_192));
+        assert_eq!(context_window("o1-mini-2024-09-12"), Some(128_000));
+        assert_eq!(context_window("o1-2024-12-17"), Some(200_000));
+        assert_eq!(context_window("openrouter/claude-3-5-sonnet"), Some(200_000));
+        assert_eq!(context_window("some-local-model"), None);
+    }
+}
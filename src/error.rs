@@ -0,0 +1,272 @@
+use reqwest::StatusCode;
+use std::fmt;
+
+// Failures talking to an LLM provider. Providers return these wrapped in anyhow::Error;
+// callers that care about the cause downcast with `error.downcast_ref::<LlmError>()`.
+#[derive(Debug)]
+pub enum LlmError {
+    Auth(ApiError),
+    RateLimit(ApiError),
+    ContextLength(ApiError),
+    // Wrong base_url: 404s, HTML pages, Cloudflare challenges
+    BadEndpoint(ApiError),
+    Server(ApiError),
+    // Any other rejected request, e.g. an unsupported parameter
+    Request(ApiError),
+    Network(reqwest::Error),
+    InvalidResponse {
+        message: String,
+        body: String,
+    },
+}
+
+// What the provider told us about a failed request
+#[derive(Debug, Clone)]
+pub struct ApiError {
+    // Missing for errors reported inside a stream that already started with 200 OK
+    pub status: Option<StatusCode>,
+    // Provider error code or type, e.g. "invalid_api_key" or "overloaded_error"
+    pub code: Option<String>,
+    pub message: Option<String>,
+    pub body: String,
+}
+
+impl LlmError {
+    pub async fn from_response(response: reqwest::Response) -> Self {
+        let status = response.status();
+        match response.text().await {
+            Ok(body) => Self::from_body(Some(status), body),
+            Err(e) => Self::Network(e),
+        }
+    }
+
+    // Classifies an error body by status code and the provider's error code
+    pub fn from_body(status: Option<StatusCode>, body: String) -> Self {
+        let error = ApiError::parse(status, body);
+        let code = error.code.as_deref().unwrap_or("").to_lowercase();
+        let status = status.map(|s| s.as_u16());
+
+        // Rate limits come first: tokens-per-minute limits mention "too many tokens" as well
+        if status == Some(429)
+            || matches!(code.as_str(), "rate_limit_error" | "rate_limit_exceeded" | "insufficient_quota" | "resource_exhausted")
+        {
+            Self::RateLimit(error)
+        } else if code == "context_length_exceeded"
+            || (matches!(status, Some(400 | 413)) && error.mentions_context_length())
+        {
+            Self::ContextLength(error)
+        } else if error.is_html() && !matches!(status, Some(500..=599)) {
+            // Challenge pages and wrong hosts come back as HTML, often with a 403
+            Self::BadEndpoint(error)
+        } else if matches!(status, Some(401 | 403))
+            || matches!(code.as_str(), "invalid_api_key" | "authentication_error" | "permission_error" | "unauthenticated" | "permission_denied")
+            // Gemini answers a bad key with 400 INVALID_ARGUMENT
+            || error.body.contains("API_KEY_INVALID")
+        {
+            Self::Auth(error)
+        } else if matches!(status, Some(408 | 500..=599))
+            || matches!(code.as_str(), "overloaded_error" | "api_error" | "server_error" | "internal" | "unavailable")
+        {
+            Self::Server(error)
+        } else if matches!(status, Some(404 | 405)) {
+            Self::BadEndpoint(error)
+        } else {
+            Self::Request(error)
+        }
+    }
+
+    pub fn invalid_response(message: impl Into<String>, body: impl Into<String>) -> Self {
+        Self::InvalidResponse {
+            message: message.into(),
+            body: body.into(),
+        }
+    }
+
+    pub fn api_error(&self) -> Option<&ApiError> {
+        match self {
+            Self::Auth(error)
+            | Self::RateLimit(error)
+            | Self::ContextLength(error)
+            | Self::BadEndpoint(error)
+            | Self::Server(error)
+            | Self::Request(error) => Some(error),
+            Self::Network(_) | Self::InvalidResponse { .. } => None,
+        }
+    }
+
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Self::Network(e) => e.status(),
+            _ => self.api_error().and_then(|error| error.status),
+        }
+    }
+
+    // Whether the same request may succeed if sent again later
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            // An exhausted billing quota does not come back by waiting
+            Self::RateLimit(error) => error.code.as_deref() != Some("insufficient_quota"),
+            Self::Server(_) => true,
+            Self::Network(e) => e.is_connect() || e.is_timeout() || e.is_request(),
+            _ => false,
+        }
+    }
+}
+
+impl From<reqwest::Error> for LlmError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_decode() {
+            Self::invalid_response(error.to_string(), "")
+        } else {
+            Self::Network(error)
+        }
+    }
+}
+
+impl ApiError {
+    // Understands the OpenAI, Anthropic and Gemini error shapes:
+    // {"error": {"message", "type" | "code" | "status"}} and {"error": "message"}
+    fn parse(status: Option<StatusCode>, body: String) -> Self {
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap_or_default();
+        let error = &json["error"];
+
+        let code = ["code", "type", "status"].iter()
+            .find_map(|key| error[key].as_str())
+            .map(str::to_string);
+        let message = error["message"].as_str()
+            .or_else(|| error.as_str())
+            .or_else(|| json["message"].as_str())
+            .map(str::to_string);
+
+        Self { status, code, message, body }
+    }
+
+    pub fn is_html(&self) -> bool {
+        let start = self.body.trim_start().to_lowercase();
+        start.starts_with("<!doctype html") || start.starts_with("<html")
+    }
+
+    pub fn is_cloudflare(&self) -> bool {
+        self.is_html() && (self.body.contains("cloudflare") || self.body.contains("Just a moment"))
+    }
+
+    fn mentions_context_length(&self) -> bool {
+        let message = self.message.as_deref().unwrap_or("").to_lowercase();
+        ["context length", "context_length", "context window", "prompt is too long", "too many tokens"]
+            .iter()
+            .any(|phrase| message.contains(phrase))
+    }
+}
+
+impl fmt::Display for LlmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Auth(error) => write!(f, "Authentication failed. Please check your API key.{}", error),
+            Self::RateLimit(error) => write!(f, "Rate limit exceeded. Please wait and try again.{}", error),
+            Self::ContextLength(error) => write!(f, "The conversation is too long for the model's context window.{}", error),
+            Self::BadEndpoint(error) if error.is_cloudflare() => write!(
+                f,
+                "Cloudflare protection detected. The API endpoint may be incorrect or blocked.{}\nPlease check your base_url configuration.",
+                error
+            ),
+            Self::BadEndpoint(error) if error.is_html() => {
+                write!(f, "Received HTML instead of JSON response. The API endpoint may be incorrect.{}", error)
+            }
+            Self::BadEndpoint(error) => write!(f, "API endpoint not found. Please check your base_url configuration.{}", error),
+            Self::Server(error) => write!(f, "Server error. The API service may be temporarily unavailable.{}", error),
+            Self::Request(error) => write!(f, "API request failed.{}", error),
+            Self::Network(e) => write!(f, "Network error: {}", e),
+            Self::InvalidResponse { message, body } if body.trim().is_empty() => {
+                write!(f, "Invalid response from provider: {}", message)
+            }
+            Self::InvalidResponse { message, body } => {
+                write!(f, "Invalid response from provider: {}\nResponse: {}", message, truncate(body))
+            }
+        }
+    }
+}
+
+// Appended to the summary line of an LlmError
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(status) = self.status {
+            write!(f, "\nStatus: {}", status)?;
+        }
+        if let Some(code) = &self.code {
+            write!(f, "\nCode: {}", code)?;
+        }
+        match &self.message {
+            Some(message) => write!(f, "\nDetails: {}", message),
+            None if !self.body.trim().is_empty() && !self.is_html() => {
+                write!(f, "\nResponse: {}", truncate(&self.body))
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for LlmError {}
+
+fn truncate(body: &str) -> String {
+    if body.chars().count() > 500 {
+        format!("{}...", body.chars().take(500).collect::<String>())
+    } else {
+        body.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classify(status: u16, body: &str) -> LlmError {
+        LlmError::from_body(Some(StatusCode::from_u16(status).unwrap()), body.to_string())
+    }
+
+    #[test]
+    fn classifies_openai_errors() {
+        let error = classify(401, r#"{"error":{"message":"Incorrect API key","type":"invalid_request_error","code":"invalid_api_key"}}"#);
+        assert!(matches!(error, LlmError::Auth(_)));
+        assert_eq!(error.api_error().unwrap().code.as_deref(), Some("invalid_api_key"));
+
+        let error = classify(400, r#"{"error":{"message":"This model's maximum context length is 8192 tokens","code":"context_length_exceeded"}}"#);
+        assert!(matches!(error, LlmError::ContextLength(_)));
+
+        let error = classify(429, r#"{"error":{"message":"You exceeded your current quota","code":"insufficient_quota"}}"#);
+        assert!(matches!(error, LlmError::RateLimit(_)));
+        assert!(!error.is_retryable());
+
+        // A tokens-per-minute limit is a rate limit even though it mentions too many tokens
+        let error = classify(429, r#"{"error":{"message":"Rate limit reached for gpt-4o on tokens per min (TPM): Limit 30000, Used 29000, Requested 2000. Too many tokens per min.","type":"tokens","code":"rate_limit_exceeded"}}"#);
+        assert!(matches!(error, LlmError::RateLimit(_)));
+        assert!(error.is_retryable());
+    }
+
+    #[test]
+    fn classifies_anthropic_errors() {
+        let error = classify(529, r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#);
+        assert!(matches!(error, LlmError::Server(_)));
+        assert!(error.is_retryable());
+
+        let error = classify(400, r#"{"type":"error","error":{"type":"invalid_request_error","message":"prompt is too long: 210000 tokens > 200000 maximum"}}"#);
+        assert!(matches!(error, LlmError::ContextLength(_)));
+
+        // Errors inside a stream have no status of their own
+        let error = LlmError::from_body(None, r#"{"type":"error","error":{"type":"rate_limit_error","message":"slow down"}}"#.to_string());
+        assert!(matches!(error, LlmError::RateLimit(_)));
+    }
+
+    #[test]
+    fn classifies_html_as_bad_endpoint() {
+        let error = classify(403, "<!DOCTYPE html><title>Just a moment...</title>");
+        assert!(matches!(&error, LlmError::BadEndpoint(e) if e.is_cloudflare()));
+
+        // A proxy's HTML error page for a down upstream is still worth retrying
+        let error = classify(502, "<html><body>Bad Gateway</body></html>");
+        assert!(matches!(error, LlmError::Server(_)));
+
+        let error = classify(404, "Not Found");
+        assert!(matches!(error, LlmError::BadEndpoint(_)));
+        assert!(error.to_string().contains("Response: Not Found"));
+    }
+}
@@ -0,0 +1,127 @@
+use colored::*;
+use std::fmt;
+use std::future::Future;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+// A second Ctrl+C within this window exits Crush
+const EXIT_WINDOW: Duration = Duration::from_secs(2);
+// A prompt's report and a signal this close together are the same key press
+const SAME_PRESS: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Copy, PartialEq)]
+enum Source {
+    Signal,
+    Prompt,
+}
+
+static INTERRUPTS: OnceLock<Notify> = OnceLock::new();
+static LAST_PRESS: Mutex<Option<(Instant, Source)>> = Mutex::new(None);
+
+// Takes over Ctrl+C for the rest of the process: the first press cancels the running
+// request or tool, a second press in quick succession exits
+pub fn install() {
+    if INTERRUPTS.set(Notify::new()).is_err() {
+        return;
+    }
+
+    tokio::spawn(async {
+        while tokio::signal::ctrl_c().await.is_ok() {
+            handle(Source::Signal);
+        }
+    });
+}
+
+// Handles Ctrl+C pressed at a prompt. Prompts read keys in raw mode, where Ctrl+C arrives as
+// an Interrupted error instead of a signal. Some terminal libraries raise SIGINT for it as
+// well, which is then not counted again.
+pub fn press() {
+    handle(Source::Prompt);
+}
+
+fn handle(source: Source) {
+    // Ctrl+C is only taken over in interactive sessions
+    let Some(notify) = INTERRUPTS.get() else {
+        return;
+    };
+
+    let now = Instant::now();
+    let previous = {
+        let mut last = LAST_PRESS.lock().unwrap();
+        let previous = *last;
+        if previous.is_some_and(|(at, from)| from != source && now - at < SAME_PRESS) {
+            return;
+        }
+        *last = Some((now, source));
+        previous
+    };
+    if previous.is_some_and(|(at, _)| now - at < EXIT_WINDOW) {
+        println!("\n{}", "Goodbye! 👋".bright_magenta());
+        std::process::exit(130);
+    }
+
+    println!("\n{}", "(Press Ctrl+C again to exit)".dimmed());
+    notify.notify_waiters();
+}
+
+// Runs the future until it finishes or Ctrl+C is pressed. Returns None when interrupted,
+// after dropping the future.
+pub async fn cancellable<F: Future>(future: F) -> Option<F::Output> {
+    match INTERRUPTS.get() {
+        Some(notify) => until_notified(notify, future).await,
+        None => Some(future.await),
+    }
+}
+
+async fn until_notified<F: Future>(notify: &Notify, future: F) -> Option<F::Output> {
+    // A future that finished on the same press, e.g. a prompt reporting Ctrl+C, keeps its
+    // own result
+    tokio::select! {
+        biased;
+        output = future => Some(output),
+        _ = notify.notified() => None,
+    }
+}
+
+// Returned by work that saw Ctrl+C itself, such as a prompt reading it as a key press
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Cancelled by the user")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn keeps_the_result_of_a_future_that_saw_the_press() {
+        let notify = Notify::new();
+
+        // Like a prompt on a blocking thread: it reports the press and finishes before the
+        // select is polled again, so both branches are ready
+        let (finish, finished) = tokio::sync::oneshot::channel();
+        let prompt = until_notified(&notify, async { finished.await.unwrap() });
+        let press = async {
+            tokio::task::yield_now().await;
+            notify.notify_waiters();
+            finish.send("cancelled at the prompt").unwrap();
+        };
+        let (output, _) = tokio::join!(prompt, press);
+        assert_eq!(output, Some("cancelled at the prompt"));
+
+        let running = until_notified(&notify, std::future::pending::<()>());
+        let press = async {
+            tokio::task::yield_now().await;
+            notify.notify_waiters();
+        };
+        let (output, _) = tokio::join!(running, press);
+        assert_eq!(output, None);
+    }
+}
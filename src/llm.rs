@@ -1,35 +1,53 @@
 use anyhow::{Result, anyhow};
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
+use std::sync::Arc;
 
-use crate::config::{Config, ProviderConfig, ApiType};
+use crate::config::{ApiType, CassetteMode, Config, ProviderConfig};
+use crate::context;
+use crate::pricing::{self, ModelPrice};
+use crate::message::{self, ChatMessage, ContentPart};
+use crate::providers::{self, Capabilities, CassetteProvider, Provider};
+use crate::tools::{ToolCall, ToolDefinition};
 
 #[derive(Debug, Clone)]
 pub struct LlmProvider {
     pub name: String,
     pub config: ProviderConfig,
-    client: Client,
+    backend: Arc<dyn Provider>,
+    // The configuration this provider was built from, to set it up again for another model
+    source: Arc<Config>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ChatMessage {
-    pub role: String,
-    pub content: String,
-    pub timestamp: chrono::DateTime<chrono::Utc>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatResponse {
-    pub content: String,
+    pub content: Vec<ContentPart>,
     pub usage: Option<Usage>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy)]
+pub enum StreamEvent<'a> {
+    Text(&'a str),
+    Thinking(&'a str),
+}
+
+pub type EventHandler<'a> = dyn FnMut(StreamEvent) + Send + 'a;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Usage {
+    // Includes cached prompt tokens and those written to the cache
     pub prompt_tokens: u32,
     pub completion_tokens: u32,
     pub total_tokens: u32,
+    // Prompt tokens read from the provider's cache
+    #[serde(default)]
+    pub cached_tokens: u32,
+    // Prompt tokens written to the cache, which some providers bill above the input price
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub cache_write_tokens: u32,
+}
+
+fn is_zero(count: &u32) -> bool {
+    *count == 0
 }
 
 impl LlmProvider {
@@ -37,8 +55,11 @@ impl LlmProvider {
         let provider_config = config.get_provider(provider_name)
             .ok_or_else(|| anyhow!("Provider '{}' not found in configuration", provider_name))?;
         
-        // Only check for API key if it's not Ollama (which can work without API key for local usage)
-        if provider_name != "ollama" && provider_config.api_key.is_none() {
+        // Only check for API key if it's not Ollama (which can work without API key for local usage),
+        // or a provider that never goes over the network
+        let replaying = provider_config.cassette.as_ref().is_some_and(|c| c.mode == CassetteMode::Replay);
+        let offline = matches!(provider_config.api_type, ApiType::Mock) || replaying;
+        if provider_name != "ollama" && !offline && provider_config.api_key.is_none() {
             return Err(anyhow!("API key not set for provider '{}'", provider_name));
         }
         
@@ -56,205 +77,163 @@ impl LlmProvider {
             }
         }
         
+        // Backends see the fully resolved sampling parameters and retry settings
+        let mut provider_config = provider_config.clone();
+        provider_config.parameters = config.sampling_params(&provider_config);
+        provider_config.retry = Some(provider_config.retry.unwrap_or_else(|| config.global_settings.retry.clone()));
+        // Replayed requests are still built by the provider, which may insist on a key
+        if replaying {
+            provider_config.api_key.get_or_insert_with(|| "replay".to_string());
+        }
+        
+        let create = || providers::registry().read()
+            .unwrap_or_else(|e| e.into_inner())
+            .create(&provider_config, providers::http_client(&provider_config)?);
+        let backend = match &provider_config.cassette {
+            Some(cassette) => CassetteProvider::create(cassette, create),
+            None => create(),
+        }
+        .map_err(|e| anyhow!("Invalid configuration for provider '{}': {}", provider_name, e))?;
+        
         Ok(Self {
             name: provider_name.to_string(),
-            config: provider_config.clone(),
-            client: Client::new(),
+            config: provider_config,
+            backend: Arc::from(backend),
+            source: Arc::new(config.clone()),
         })
     }
     
-    pub async fn send_message(&self, messages: &[ChatMessage]) -> Result<ChatResponse> {
-        match self.config.api_type {
-            ApiType::OpenAI => self.send_openai_message(messages).await,
-            ApiType::Anthropic => self.send_anthropic_message(messages).await,
-            ApiType::Custom => self.send_openai_message(messages).await, // Default to OpenAI format
-        }
+    // The same provider with another model, and that model's parameters
+    pub async fn with_model(&self, model: &str) -> Result<Self> {
+        let mut config = (*self.source).clone();
+        let provider_config = config.providers.get_mut(&self.name)
+            .ok_or_else(|| anyhow!("Provider '{}' not found in configuration", self.name))?;
+        provider_config.model = Some(model.to_string());
+        Self::new(&self.name, &config).await
     }
     
-    async fn send_openai_message(&self, messages: &[ChatMessage]) -> Result<ChatResponse> {
-        let base_url = self.config.base_url.as_deref().unwrap_or("https://api.openai.com/v1");
-        let model = self.config.model.as_deref().unwrap_or("gpt-4");
-        
-        let request_body = json!({
-            "model": model,
-            "messages": messages.iter().map(|m| json!({
-                "role": m.role,
-                "content": m.content
-            })).collect::<Vec<_>>(),
-            "temperature": 0.7,
-            "max_tokens": 4000
-        });
-        
-        let mut request_builder = self.client
-            .post(&format!("{}/chat/completions", base_url))
-            .header("Content-Type", "application/json");
-        
-        // Add Authorization header only if API key is present (for Ollama compatibility)
-        if let Some(api_key) = &self.config.api_key {
-            request_builder = request_builder.header("Authorization", format!("Bearer {}", api_key));
-        }
-        
-        let response = request_builder
-            .json(&request_body)
-            .send()
-            .await?;
-        
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await?;
-            
-            // Check for common error patterns
-            let error_msg = if error_text.contains("<!DOCTYPE html>") {
-                if error_text.contains("cloudflare") || error_text.contains("Just a moment") {
-                    format!("Cloudflare protection detected. The API endpoint may be incorrect or blocked.\nStatus: {}\nPlease check your base_url configuration.", status)
-                } else {
-                    format!("Received HTML instead of JSON response. The API endpoint may be incorrect.\nStatus: {}", status)
-                }
-            } else if error_text.contains("unauthorized") || error_text.contains("invalid_api_key") {
-                format!("Authentication failed. Please check your API key.\nStatus: {}", status)
-            } else if error_text.contains("rate_limit") || error_text.contains("quota") {
-                format!("Rate limit exceeded. Please wait and try again.\nStatus: {}", status)
-            } else if status == 404 {
-                format!("API endpoint not found. Please check your base_url configuration.\nStatus: {}", status)
-            } else if status == 500 || status == 502 || status == 503 {
-                format!("Server error. The API service may be temporarily unavailable.\nStatus: {}", status)
-            } else {
-                format!("API request failed.\nStatus: {}\nResponse: {}", status, 
-                    if error_text.len() > 500 { 
-                        format!("{}...", &error_text[..500])
-                    } else { 
-                        error_text 
-                    }
-                )
-            };
-            
-            return Err(anyhow!("{}", error_msg));
+    // Wraps an existing backend, so tests can keep a handle on it
+    #[cfg(test)]
+    pub fn with_backend(name: &str, config: ProviderConfig, backend: Arc<dyn Provider>) -> Self {
+        Self {
+            name: name.to_string(),
+            config,
+            backend,
+            source: Arc::new(Config::default()),
         }
-        
-        let response_body: serde_json::Value = response.json().await?;
-        
-        let content = response_body["choices"][0]["message"]["content"]
-            .as_str()
-            .ok_or_else(|| anyhow!("Invalid response format"))?
-            .to_string();
-        
-        let usage = response_body.get("usage").and_then(|u| {
-            Some(Usage {
-                prompt_tokens: u["prompt_tokens"].as_u64()? as u32,
-                completion_tokens: u["completion_tokens"].as_u64()? as u32,
-                total_tokens: u["total_tokens"].as_u64()? as u32,
-            })
-        });
-        
-        Ok(ChatResponse { content, usage })
     }
     
-    async fn send_anthropic_message(&self, messages: &[ChatMessage]) -> Result<ChatResponse> {
-        let api_key = self.config.api_key.as_ref().unwrap();
-        let base_url = self.config.base_url.as_deref().unwrap_or("https://api.anthropic.com/v1");
-        let model = self.config.model.as_deref().unwrap_or("claude-3-sonnet-20240229");
-        
-        // Convert messages to Anthropic format
-        let system_message = messages.iter()
-            .find(|m| m.role == "system")
-            .map(|m| m.content.clone());
-        
-        let conversation_messages: Vec<_> = messages.iter()
-            .filter(|m| m.role != "system")
-            .map(|m| json!({
-                "role": m.role,
-                "content": m.content
-            }))
-            .collect();
-        
-        let mut request_body = json!({
-            "model": model,
-            "messages": conversation_messages,
-            "max_tokens": 4000
-        });
-        
-        if let Some(system) = system_message {
-            request_body["system"] = json!(system);
-        }
-        
-        let response = self.client
-            .post(&format!("{}/messages", base_url))
-            .header("x-api-key", api_key)
-            .header("anthropic-version", "2023-06-01")
-            .header("Content-Type", "application/json")
-            .json(&request_body)
-            .send()
-            .await?;
-        
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await?;
-            
-            // Check for common error patterns
-            let error_msg = if error_text.contains("<!DOCTYPE html>") {
-                if error_text.contains("cloudflare") || error_text.contains("Just a moment") {
-                    format!("Cloudflare protection detected. The API endpoint may be incorrect or blocked.\nStatus: {}\nPlease check your base_url configuration.", status)
-                } else {
-                    format!("Received HTML instead of JSON response. The API endpoint may be incorrect.\nStatus: {}", status)
-                }
-            } else if error_text.contains("unauthorized") || error_text.contains("invalid_api_key") {
-                format!("Authentication failed. Please check your API key.\nStatus: {}", status)
-            } else if error_text.contains("rate_limit") || error_text.contains("quota") {
-                format!("Rate limit exceeded. Please wait and try again.\nStatus: {}", status)
-            } else if status == 404 {
-                format!("API endpoint not found. Please check your base_url configuration.\nStatus: {}", status)
-            } else if status == 500 || status == 502 || status == 503 {
-                format!("Server error. The API service may be temporarily unavailable.\nStatus: {}", status)
-            } else {
-                format!("API request failed.\nStatus: {}\nResponse: {}", status, 
-                    if error_text.len() > 500 { 
-                        format!("{}...", &error_text[..500])
-                    } else { 
-                        error_text 
-                    }
-                )
-            };
-            
-            return Err(anyhow!("{}", error_msg));
-        }
-        
-        let response_body: serde_json::Value = response.json().await?;
-        
-        let content = response_body["content"][0]["text"]
-            .as_str()
-            .ok_or_else(|| anyhow!("Invalid response format"))?
-            .to_string();
-        
-        let usage = response_body.get("usage").and_then(|u| {
-            Some(Usage {
-                prompt_tokens: u["input_tokens"].as_u64()? as u32,
-                completion_tokens: u["output_tokens"].as_u64()? as u32,
-                total_tokens: (u["input_tokens"].as_u64()? + u["output_tokens"].as_u64()?) as u32,
-            })
-        });
-        
-        Ok(ChatResponse { content, usage })
+    pub async fn send_message(&self, messages: &[ChatMessage], tools: &[ToolDefinition]) -> Result<ChatResponse> {
+        self.backend.send(messages, tools).await
+    }
+    
+    // Like send_message, but hands text to on_event as it is generated
+    pub async fn stream_message(&self, messages: &[ChatMessage], tools: &[ToolDefinition], on_event: &mut EventHandler<'_>) -> Result<ChatResponse> {
+        self.backend.stream(messages, tools, on_event).await
+    }
+    
+    pub async fn list_models(&self) -> Result<Vec<String>> {
+        self.backend.list_models().await
+    }
+    
+    pub fn capabilities(&self) -> Capabilities {
+        self.backend.capabilities()
+    }
+    
+    // Configured price for the model, or the built-in list price
+    pub fn price(&self) -> Option<ModelPrice> {
+        let model = self.config.model.as_deref()?;
+        self.config.pricing.get(model).copied()
+            .or_else(|| pricing::builtin_price(model))
+    }
+    
+    pub fn context_window(&self) -> u32 {
+        self.config.context_window
+            .or_else(|| self.config.model.as_deref().and_then(context::context_window))
+            .unwrap_or(context::DEFAULT_CONTEXT_WINDOW)
     }
 }
 
-impl ChatMessage {
-    pub fn new(role: impl Into<String>, content: impl Into<String>) -> Self {
-        Self {
-            role: role.into(),
-            content: content.into(),
-            timestamp: chrono::Utc::now(),
-        }
+impl ChatResponse {
+    pub fn text(&self) -> String {
+        message::text_of(&self.content)
     }
     
-    pub fn system(content: impl Into<String>) -> Self {
-        Self::new("system", content)
+    pub fn thinking(&self) -> String {
+        message::thinking_of(&self.content)
     }
     
-    pub fn user(content: impl Into<String>) -> Self {
-        Self::new("user", content)
+    pub fn tool_calls(&self) -> Vec<ToolCall> {
+        message::tool_calls_of(&self.content)
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SamplingParams;
     
-    pub fn assistant(content: impl Into<String>) -> Self {
-        Self::new("assistant", content)
+    #[tokio::test]
+    async fn switching_models_applies_their_parameters() {
+        let mut config = Config::default();
+        config.providers.insert("mock".to_string(), ProviderConfig {
+            api_type: ApiType::Mock,
+            model: Some("small".to_string()),
+            model_parameters: [("large".to_string(), SamplingParams { temperature: Some(0.1), ..Default::default() })].into(),
+            ..Default::default()
+        });
+        config.parameter_overrides.max_tokens = Some(123);
+        
+        let provider = LlmProvider::new("mock", &config).await.unwrap();
+        assert_eq!(provider.config.parameters.temperature, Some(0.7));
+        
+        let switched = provider.with_model("large").await.unwrap();
+        assert_eq!(switched.config.model.as_deref(), Some("large"));
+        assert_eq!(switched.config.parameters.temperature, Some(0.1));
+        // Command line overrides still win
+        assert_eq!(switched.config.parameters.max_tokens, Some(123));
     }
-}
\ No newline at end of file
+    
+    #[tokio::test]
+    async fn thinking_budget_works_with_the_default_config() {
+        let mut config = Config::default();
+        let anthropic = config.providers.get_mut("anthropic").unwrap();
+        anthropic.api_key = Some("test-key".to_string());
+        anthropic.parameters.thinking_budget = Some(8000);
+        
+        // The global temperature and max_tokens are not inherited alongside a budget
+        let provider = LlmProvider::new("anthropic", &config).await.unwrap();
+        assert_eq!(provider.config.parameters.temperature, None);
+        assert_eq!(provider.config.parameters.max_tokens, None);
+        
+        // Explicitly configured values are still checked
+        config.parameter_overrides.temperature = Some(0.5);
+        assert!(LlmProvider::new("anthropic", &config).await.is_err());
+    }
+    
+    #[tokio::test]
+    async fn reasoning_models_work_with_the_default_config() {
+        let mut config = Config::default();
+        let openai = config.providers.get_mut("openai").unwrap();
+        openai.api_key = Some("test-key".to_string());
+        openai.model = Some("o3-mini".to_string());
+        
+        let provider = LlmProvider::new("openai", &config).await.unwrap();
+        assert_eq!(provider.config.parameters.temperature, None);
+        assert_eq!(provider.config.parameters.max_tokens, None);
+        
+        config.parameter_overrides.temperature = Some(0.5);
+        assert!(LlmProvider::new("openai", &config).await.is_err());
+    }
+    
+    #[tokio::test]
+    async fn fallbacks_ignore_a_thinking_budget_they_do_not_support() {
+        let mut config = Config::default();
+        config.providers.get_mut("deepseek").unwrap().api_key = Some("test-key".to_string());
+        config.parameter_overrides.thinking_budget = Some(2048);
+        
+        assert!(LlmProvider::new("deepseek", &config).await.is_err());
+        let fallback = LlmProvider::new("deepseek", &config.for_fallback("deepseek")).await.unwrap();
+        assert_eq!(fallback.config.parameters.thinking_budget, None);
+    }
+}
@@ -1,11 +1,20 @@
-use clap::{Parser, Subcommand};
+use clap::Parser;
 use anyhow::Result;
 use colored::*;
 
 mod cli;
 mod config;
+mod context;
+mod error;
+mod interrupt;
 mod llm;
+mod message;
+mod models;
+mod pricing;
+mod providers;
+mod retry;
 mod session;
+mod sse;
 mod tools;
 
 use cli::Cli;
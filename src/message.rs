@@ -0,0 +1,291 @@
+use serde::{Deserialize, Serialize};
+
+use crate::llm::Usage;
+use crate::tools::{ToolCall, ToolResult};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+    Tool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    Text {
+        text: String,
+    },
+    ToolCall(ToolCall),
+    ToolResult {
+        tool_call_id: String,
+        name: String,
+        #[serde(flatten)]
+        result: ToolResult,
+    },
+    // Base64-encoded image data
+    Image {
+        media_type: String,
+        data: String,
+    },
+    // The model's reasoning before it answered. Some APIs need it sent back, with the
+    // signature they issued, to continue a turn that uses tools.
+    Thinking {
+        text: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        signature: Option<String>,
+        // Thinking the provider flagged and encrypted; `text` holds the encrypted data
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        redacted: bool,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "StoredChatMessage")]
+pub struct ChatMessage {
+    pub role: Role,
+    pub content: Vec<ContentPart>,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    #[serde(default, skip_serializing_if = "MessageMetadata::is_empty")]
+    pub metadata: MessageMetadata,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MessageMetadata {
+    // Provider and model that produced an assistant message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+    // In USD, priced when the request was made
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+    // What prompt caching saved on this request in USD, when the provider cached anything
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_savings: Option<f64>,
+    // Cut short by Ctrl+C: a partial reply, or tool results for calls that did not finish
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub interrupted: bool,
+
+    // Keeps keys written by newer versions when an older one rewrites the session
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl MessageMetadata {
+    pub fn is_empty(&self) -> bool {
+        self.provider.is_none()
+            && self.model.is_none()
+            && self.usage.is_none()
+            && self.cost.is_none()
+            && self.cache_savings.is_none()
+            && !self.interrupted
+            && self.extra.is_empty()
+    }
+}
+
+impl ChatMessage {
+    pub fn new(role: Role, content: Vec<ContentPart>) -> Self {
+        Self {
+            role,
+            content,
+            timestamp: chrono::Utc::now(),
+            metadata: MessageMetadata::default(),
+        }
+    }
+
+    pub fn system(content: impl Into<String>) -> Self {
+        Self::new(Role::System, vec![ContentPart::text(content)])
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self::new(Role::User, vec![ContentPart::text(content)])
+    }
+
+    pub fn assistant(content: Vec<ContentPart>) -> Self {
+        Self::new(Role::Assistant, content)
+    }
+
+    pub fn tool_results(results: Vec<(ToolCall, ToolResult)>) -> Self {
+        Self::new(Role::Tool, results.into_iter()
+            .map(|(call, result)| ContentPart::ToolResult {
+                tool_call_id: call.id,
+                name: call.name,
+                result,
+            })
+            .collect())
+    }
+
+    // All text parts joined together, ignoring tool calls, images and thinking
+    pub fn text(&self) -> String {
+        text_of(&self.content)
+    }
+
+    pub fn tool_calls(&self) -> Vec<ToolCall> {
+        tool_calls_of(&self.content)
+    }
+}
+
+impl ContentPart {
+    pub fn text(text: impl Into<String>) -> Self {
+        ContentPart::Text { text: text.into() }
+    }
+}
+
+pub fn text_of(parts: &[ContentPart]) -> String {
+    parts.iter()
+        .filter_map(|part| match part {
+            ContentPart::Text { text } => Some(text.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Readable thinking parts joined together
+pub fn thinking_of(parts: &[ContentPart]) -> String {
+    parts.iter()
+        .filter_map(|part| match part {
+            ContentPart::Thinking { text, redacted: false, .. } if !text.is_empty() => Some(text.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn tool_calls_of(parts: &[ContentPart]) -> Vec<ToolCall> {
+    parts.iter()
+        .filter_map(|part| match part {
+            ContentPart::ToolCall(call) => Some(call.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+// On-disk shape of a message. Sessions saved before content parts existed store `content`
+// as a plain string, with tool calls and tool results in separate fields.
+#[derive(Deserialize)]
+struct StoredChatMessage {
+    role: Role,
+    content: StoredContent,
+    timestamp: chrono::DateTime<chrono::Utc>,
+    #[serde(default)]
+    metadata: MessageMetadata,
+    #[serde(default)]
+    tool_calls: Vec<ToolCall>,
+    #[serde(default)]
+    tool_call_id: Option<String>,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+impl From<StoredChatMessage> for ChatMessage {
+    fn from(stored: StoredChatMessage) -> Self {
+        let content = match stored.content {
+            StoredContent::Parts(parts) => parts,
+            StoredContent::Text(text) => match stored.tool_call_id {
+                Some(tool_call_id) => vec![ContentPart::ToolResult {
+                    tool_call_id,
+                    name: stored.name.unwrap_or_default(),
+                    result: ToolResult {
+                        success: !text.starts_with("Tool failed."),
+                        output: text,
+                        error: None,
+                    },
+                }],
+                None => {
+                    let mut parts = Vec::new();
+                    if !text.is_empty() {
+                        parts.push(ContentPart::text(text));
+                    }
+                    parts.extend(stored.tool_calls.into_iter().map(ContentPart::ToolCall));
+                    parts
+                }
+            },
+        };
+
+        Self {
+            role: stored.role,
+            content,
+            timestamp: stored.timestamp,
+            metadata: stored.metadata,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(json: serde_json::Value) -> ChatMessage {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn loads_sessions_saved_with_plain_text_content() {
+        let timestamp = "2024-05-01T12:00:00Z";
+
+        let message = load(serde_json::json!({ "role": "user", "content": "List the files", "timestamp": timestamp }));
+        assert_eq!(message.role, Role::User);
+        assert!(matches!(message.content.as_slice(), [ContentPart::Text { text }] if text == "List the files"));
+        assert!(message.metadata.is_empty());
+
+        let message = load(serde_json::json!({
+            "role": "assistant",
+            "content": "",
+            "timestamp": timestamp,
+            "tool_calls": [{ "id": "call_1", "name": "ls", "arguments": { "path": "." }, "description": null }]
+        }));
+        assert!(matches!(
+            message.content.as_slice(),
+            [ContentPart::ToolCall(call)] if call.id == "call_1" && call.name == "ls" && call.arguments["path"] == "."
+        ));
+
+        let message = load(serde_json::json!({
+            "role": "tool",
+            "content": "Cargo.toml\nsrc",
+            "timestamp": timestamp,
+            "tool_call_id": "call_1",
+            "name": "ls"
+        }));
+        assert_eq!(message.role, Role::Tool);
+        let [ContentPart::ToolResult { tool_call_id, name, result }] = message.content.as_slice() else {
+            panic!("expected a tool result, got {:?}", message.content);
+        };
+        assert_eq!((tool_call_id.as_str(), name.as_str()), ("call_1", "ls"));
+        assert!(result.success);
+        assert_eq!(result.output, "Cargo.toml\nsrc");
+
+        let message = load(serde_json::json!({
+            "role": "tool",
+            "content": "Tool failed. No such file or directory",
+            "timestamp": timestamp,
+            "tool_call_id": "call_2"
+        }));
+        assert!(matches!(message.content.as_slice(), [ContentPart::ToolResult { result, .. }] if !result.success));
+    }
+
+    #[test]
+    fn saved_messages_load_back_unchanged() {
+        let message = load(serde_json::json!({
+            "role": "assistant",
+            "content": "Let me look.",
+            "timestamp": "2024-05-01T12:00:00Z",
+            "tool_calls": [{ "id": "call_1", "name": "ls", "arguments": {}, "description": null }]
+        }));
+        let saved = serde_json::to_value(&message).unwrap();
+        assert_eq!(saved["content"][0], serde_json::json!({ "type": "text", "text": "Let me look." }));
+        assert_eq!(serde_json::to_value(load(saved.clone())).unwrap(), saved);
+    }
+}
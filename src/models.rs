@@ -0,0 +1,99 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tokio::fs;
+
+use crate::llm::LlmProvider;
+
+// Model lists are fetched again after this long
+const CACHE_TTL_HOURS: i64 = 24;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ModelCache {
+    providers: HashMap<String, CachedModels>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedModels {
+    // The list is fetched again when the provider is pointed somewhere else
+    base_url: Option<String>,
+    fetched_at: DateTime<Utc>,
+    models: Vec<String>,
+}
+
+impl CachedModels {
+    fn is_fresh(&self, base_url: Option<&str>, now: DateTime<Utc>) -> bool {
+        self.base_url.as_deref() == base_url && now - self.fetched_at < Duration::hours(CACHE_TTL_HOURS)
+    }
+}
+
+// The models the provider offers, from the cache unless it is stale or `refresh` is set
+pub async fn list(provider: &LlmProvider, refresh: bool) -> Result<Vec<String>> {
+    let mut cache = load().await;
+    let base_url = provider.config.base_url.as_deref();
+
+    if !refresh {
+        if let Some(cached) = cache.providers.get(&provider.name) {
+            if cached.is_fresh(base_url, Utc::now()) {
+                return Ok(cached.models.clone());
+            }
+        }
+    }
+
+    let models = provider.list_models().await?;
+    if !models.is_empty() {
+        cache.providers.insert(provider.name.clone(), CachedModels {
+            base_url: base_url.map(str::to_string),
+            fetched_at: Utc::now(),
+            models: models.clone(),
+        });
+        // Failing to write the cache only means fetching again next time
+        let _ = save(&cache).await;
+    }
+
+    Ok(models)
+}
+
+async fn load() -> ModelCache {
+    match fs::read_to_string(cache_path()).await {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_default(),
+        Err(_) => ModelCache::default(),
+    }
+}
+
+async fn save(cache: &ModelCache) -> Result<()> {
+    let path = cache_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    fs::write(&path, serde_json::to_string_pretty(cache)?).await?;
+    Ok(())
+}
+
+fn cache_path() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("crush")
+        .join("models.json")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cached_lists_expire_and_follow_the_base_url() {
+        let now = Utc::now();
+        let cached = CachedModels {
+            base_url: Some("http://localhost:11434/v1".to_string()),
+            fetched_at: now - Duration::hours(2),
+            models: vec!["llama3.2".to_string()],
+        };
+
+        assert!(cached.is_fresh(Some("http://localhost:11434/v1"), now));
+        assert!(!cached.is_fresh(Some("http://gpu-box:11434/v1"), now));
+        assert!(!cached.is_fresh(Some("http://localhost:11434/v1"), now + Duration::hours(CACHE_TTL_HOURS)));
+    }
+}
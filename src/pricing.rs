@@ -0,0 +1,268 @@
+use colored::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::llm::Usage;
+use crate::message::ChatMessage;
+
+// Prices in USD per million tokens
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+    // Prompt tokens read from the provider's cache; billed as input when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cached_input: Option<f64>,
+    // Prompt tokens written to the cache; billed as input when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_write: Option<f64>,
+}
+
+const fn price(input: f64, output: f64, cached_input: f64) -> ModelPrice {
+    ModelPrice { input, output, cached_input: Some(cached_input), cache_write: None }
+}
+
+// Anthropic bills cache writes (with the default five minute lifetime) at 1.25x input
+const fn anthropic_price(input: f64, output: f64, cached_input: f64, cache_write: f64) -> ModelPrice {
+    ModelPrice { input, output, cached_input: Some(cached_input), cache_write: Some(cache_write) }
+}
+
+// List prices by model id prefix. More specific prefixes come first. Providers can
+// override these, or price other models, with `pricing` in their configuration.
+const PRICES: &[(&str, ModelPrice)] = &[
+    ("gpt-5-pro", price(15.00, 120.00, 15.00)),
+    ("gpt-5-nano", price(0.05, 0.40, 0.005)),
+    ("gpt-5-mini", price(0.25, 2.00, 0.025)),
+    ("gpt-5", price(1.25, 10.00, 0.125)),
+    ("gpt-4.1-nano", price(0.10, 0.40, 0.025)),
+    ("gpt-4.1-mini", price(0.40, 1.60, 0.10)),
+    ("gpt-4.1", price(2.00, 8.00, 0.50)),
+    ("gpt-4o-mini", price(0.15, 0.60, 0.075)),
+    ("gpt-4o", price(2.50, 10.00, 1.25)),
+    ("gpt-4-turbo", price(10.00, 30.00, 10.00)),
+    ("gpt-4", price(30.00, 60.00, 30.00)),
+    ("gpt-3.5-turbo", price(0.50, 1.50, 0.50)),
+    ("o1-mini", price(1.10, 4.40, 0.55)),
+    ("o1", price(15.00, 60.00, 7.50)),
+    ("o3-mini", price(1.10, 4.40, 0.55)),
+    ("o3", price(2.00, 8.00, 0.50)),
+    ("o4-mini", price(1.10, 4.40, 0.275)),
+    ("claude-3-haiku", anthropic_price(0.25, 1.25, 0.03, 0.30)),
+    ("claude-3-5-haiku", anthropic_price(0.80, 4.00, 0.08, 1.00)),
+    ("claude-haiku-4", anthropic_price(1.00, 5.00, 0.10, 1.25)),
+    ("claude-3-sonnet", anthropic_price(3.00, 15.00, 0.30, 3.75)),
+    ("claude-3-5-sonnet", anthropic_price(3.00, 15.00, 0.30, 3.75)),
+    ("claude-3-7-sonnet", anthropic_price(3.00, 15.00, 0.30, 3.75)),
+    ("claude-sonnet-4", anthropic_price(3.00, 15.00, 0.30, 3.75)),
+    ("claude-3-opus", anthropic_price(15.00, 75.00, 1.50, 18.75)),
+    ("claude-opus-4-5", anthropic_price(5.00, 25.00, 0.50, 6.25)),
+    ("claude-opus-4", anthropic_price(15.00, 75.00, 1.50, 18.75)),
+    ("gemini-2.5-pro", price(1.25, 10.00, 0.31)),
+    ("gemini-2.5-flash-lite", price(0.10, 0.40, 0.025)),
+    ("gemini-2.5-flash", price(0.30, 2.50, 0.075)),
+    ("gemini-2.0-flash-lite", price(0.075, 0.30, 0.075)),
+    ("gemini-2.0-flash", price(0.10, 0.40, 0.025)),
+    ("deepseek-chat", price(0.27, 1.10, 0.07)),
+    ("deepseek-reasoner", price(0.55, 2.19, 0.14)),
+    ("moonshot-v1-8k", price(0.20, 2.00, 0.20)),
+];
+
+pub fn builtin_price(model: &str) -> Option<ModelPrice> {
+    let model = model.to_lowercase();
+    let model = model.rsplit('/').next().unwrap_or(&model);
+    PRICES.iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .map(|(_, price)| *price)
+}
+
+impl ModelPrice {
+    // Cost of one request in USD
+    pub fn cost(&self, usage: &Usage) -> f64 {
+        let cached = usage.cached_tokens.min(usage.prompt_tokens);
+        let written = usage.cache_write_tokens.min(usage.prompt_tokens - cached);
+        let uncached = (usage.prompt_tokens - cached - written) as f64;
+        let cached_input = self.cached_input.unwrap_or(self.input);
+        let cache_write = self.cache_write.unwrap_or(self.input);
+        (uncached * self.input
+            + cached as f64 * cached_input
+            + written as f64 * cache_write
+            + usage.completion_tokens as f64 * self.output) / 1_000_000.0
+    }
+    
+    // What caching saved on one request in USD, compared to sending the whole prompt at the
+    // input price. Negative when writing to the cache cost more than reading from it saved.
+    pub fn cache_savings(&self, usage: &Usage) -> f64 {
+        let uncached = Usage {
+            cached_tokens: 0,
+            cache_write_tokens: 0,
+            ..usage.clone()
+        };
+        self.cost(&uncached) - self.cost(usage)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UsageTotals {
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cached_tokens: u64,
+    pub cache_write_tokens: u64,
+    pub cost: f64,
+    pub cache_savings: f64,
+    // Requests made with a model that has no known price
+    pub unpriced: u64,
+}
+
+impl UsageTotals {
+    pub fn add(&mut self, usage: &Usage, cost: Option<f64>, cache_savings: Option<f64>) {
+        self.requests += 1;
+        self.prompt_tokens += usage.prompt_tokens as u64;
+        self.completion_tokens += usage.completion_tokens as u64;
+        self.cached_tokens += usage.cached_tokens as u64;
+        self.cache_write_tokens += usage.cache_write_tokens as u64;
+        match cost {
+            Some(cost) => self.cost += cost,
+            None => self.unpriced += 1,
+        }
+        self.cache_savings += cache_savings.unwrap_or_default();
+    }
+
+    pub fn merge(&mut self, other: &UsageTotals) {
+        self.requests += other.requests;
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.cached_tokens += other.cached_tokens;
+        self.cache_write_tokens += other.cache_write_tokens;
+        self.cost += other.cost;
+        self.cache_savings += other.cache_savings;
+        self.unpriced += other.unpriced;
+    }
+
+    // One-line summary, e.g. "12 requests, 48210 prompt (30000 cached) + 1830 completion tokens, $0.0931"
+    pub fn summary(&self) -> String {
+        let cached = match (self.cached_tokens, self.cache_write_tokens) {
+            (0, 0) => String::new(),
+            (read, 0) => format!(" ({} cached)", read),
+            (read, written) => format!(" ({} cached, {} written to cache)", read, written),
+        };
+        let savings = if self.cache_savings.abs() >= 0.00005 {
+            let verb = if self.cache_savings > 0.0 { "saved" } else { "cost" };
+            format!(" (caching {} ${:.4})", verb, self.cache_savings.abs())
+        } else {
+            String::new()
+        };
+        let unpriced = if self.unpriced > 0 {
+            format!(" + {} unpriced", self.unpriced)
+        } else {
+            String::new()
+        };
+        format!(
+            "{} requests, {} prompt{} + {} completion tokens, ${:.4}{}{}",
+            self.requests, self.prompt_tokens, cached, self.completion_tokens, self.cost, savings, unpriced
+        )
+    }
+}
+
+// Token usage and spend, broken down by provider and by (local) day
+#[derive(Debug, Clone, Default)]
+pub struct UsageReport {
+    pub total: UsageTotals,
+    pub by_provider: BTreeMap<String, UsageTotals>,
+    pub by_day: BTreeMap<chrono::NaiveDate, UsageTotals>,
+}
+
+impl UsageReport {
+    pub fn add_message(&mut self, message: &ChatMessage) {
+        let Some(usage) = &message.metadata.usage else {
+            return;
+        };
+        let cost = message.metadata.cost;
+        let savings = message.metadata.cache_savings;
+        let provider = message.metadata.provider.clone().unwrap_or_else(|| "unknown".to_string());
+        let day = message.timestamp.with_timezone(&chrono::Local).date_naive();
+
+        self.total.add(usage, cost, savings);
+        self.by_provider.entry(provider).or_default().add(usage, cost, savings);
+        self.by_day.entry(day).or_default().add(usage, cost, savings);
+    }
+
+    pub fn merge(&mut self, other: &UsageReport) {
+        self.total.merge(&other.total);
+        for (provider, totals) in &other.by_provider {
+            self.by_provider.entry(provider.clone()).or_default().merge(totals);
+        }
+        for (day, totals) in &other.by_day {
+            self.by_day.entry(*day).or_default().merge(totals);
+        }
+    }
+
+    pub fn print(&self, indent: &str) {
+        println!("{}Total: {}", indent, self.total.summary().bright_white());
+        if self.by_provider.len() > 1 {
+            println!("{}By provider:", indent);
+            for (provider, totals) in &self.by_provider {
+                println!("{}  {}: {}", indent, provider.bright_white(), totals.summary());
+            }
+        }
+        if !self.by_day.is_empty() {
+            println!("{}By day:", indent);
+            for (day, totals) in &self.by_day {
+                println!("{}  {}: {}", indent, day.to_string().bright_white(), totals.summary());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn prices_cached_tokens_separately() {
+        let usage = Usage {
+            prompt_tokens: 1_000_000,
+            completion_tokens: 100_000,
+            total_tokens: 1_100_000,
+            cached_tokens: 400_000,
+            cache_write_tokens: 0,
+        };
+        let price = builtin_price("claude-sonnet-4-20250514").unwrap();
+        // 600k uncached at $3, 400k cached at $0.30, 100k completion at $15
+        assert!((price.cost(&usage) - (1.8 + 0.12 + 1.5)).abs() < 1e-9);
+
+        let no_cache_price = ModelPrice { input: 1.0, output: 2.0, cached_input: None, cache_write: None };
+        assert!((no_cache_price.cost(&usage) - 1.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn prices_cache_writes_and_savings() {
+        let price = builtin_price("claude-sonnet-4-5").unwrap();
+        // First request writes 10k tokens to the cache, the second reads them back
+        let write = Usage { prompt_tokens: 10_100, completion_tokens: 0, total_tokens: 10_100, cached_tokens: 0, cache_write_tokens: 10_000 };
+        let read = Usage { prompt_tokens: 10_200, completion_tokens: 0, total_tokens: 10_200, cached_tokens: 10_000, cache_write_tokens: 0 };
+
+        assert!((price.cost(&write) - (0.0003 + 0.0375)).abs() < 1e-9);
+        assert!((price.cache_savings(&write) + 0.0075).abs() < 1e-9);
+        assert!((price.cache_savings(&read) - 0.027).abs() < 1e-9);
+    }
+
+    #[test]
+    fn matches_the_most_specific_prefix() {
+        assert_eq!(builtin_price("gpt-4o-mini-2024-07-18").unwrap().input, 0.15);
+        assert_eq!(builtin_price("gpt-4o-2024-08-06").unwrap().input, 2.50);
+        assert_eq!(builtin_price("llama3.2"), None);
+        assert_eq!(builtin_price("gpt-5-mini-2025-08-07").unwrap().input, 0.25);
+        assert_eq!(builtin_price("gpt-5.1").unwrap().input, 1.25);
+    }
+
+    #[test]
+    fn prices_every_default_model() {
+        for (name, provider) in Config::default().providers {
+            let model = provider.model.unwrap();
+            let price = provider.pricing.get(&model).copied().or_else(|| builtin_price(&model));
+            assert!(price.is_some(), "{} has no price for {}", name, model);
+        }
+    }
+}
@@ -0,0 +1,605 @@
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use reqwest::Client;
+use serde_json::json;
+use std::collections::BTreeMap;
+
+use super::{check_range, read_json, Capabilities, Provider};
+use crate::config::ProviderConfig;
+use crate::llm::{ChatResponse, EventHandler, StreamEvent, Usage};
+use crate::message::{ChatMessage, ContentPart, Role};
+use crate::error::LlmError;
+use crate::retry::{self, RetrySettings};
+use crate::sse::SseParser;
+use crate::tools::{ToolCall, ToolDefinition};
+
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+const DEFAULT_MAX_TOKENS: u32 = 4096;
+const MIN_THINKING_BUDGET: u32 = 1024;
+// The API allows at most four cache breakpoints per request
+const CACHED_TURNS: usize = 2;
+
+// Anthropic Messages API
+#[derive(Debug)]
+pub struct AnthropicProvider {
+    config: ProviderConfig,
+    client: Client,
+}
+
+impl AnthropicProvider {
+    pub fn create(config: &ProviderConfig, client: Client) -> Result<Box<dyn Provider>> {
+        if config.api_key.is_none() {
+            return Err(anyhow!("The Anthropic API requires an api_key"));
+        }
+        
+        let params = &config.parameters;
+        check_range("temperature", params.temperature, 0.0, 1.0)?;
+        check_range("top_p", params.top_p, 0.0, 1.0)?;
+        if params.max_tokens == Some(0) {
+            return Err(anyhow!("max_tokens must be greater than 0"));
+        }
+        if params.seed.is_some() {
+            return Err(anyhow!("seed is not supported by the Anthropic API"));
+        }
+        if params.reasoning_effort.is_some() {
+            return Err(anyhow!("reasoning_effort is not supported by the Anthropic API; set thinking_budget instead"));
+        }
+        if let Some(budget) = params.thinking_budget {
+            if budget < MIN_THINKING_BUDGET {
+                return Err(anyhow!("thinking_budget must be at least {} for the Anthropic API", MIN_THINKING_BUDGET));
+            }
+            if params.max_tokens.is_some_and(|max_tokens| max_tokens <= budget) {
+                return Err(anyhow!("max_tokens must be greater than thinking_budget, which counts towards it"));
+            }
+            // Extended thinking only works with the default sampling
+            if params.temperature.is_some() {
+                return Err(anyhow!("temperature cannot be set together with thinking_budget for the Anthropic API"));
+            }
+            check_range("top_p", params.top_p, 0.95, 1.0)?;
+        }
+        
+        Ok(Box::new(Self {
+            config: config.clone(),
+            client,
+        }))
+    }
+    
+    fn retry_settings(&self) -> RetrySettings {
+        self.config.retry.clone().unwrap_or_default()
+    }
+    
+    fn base_url(&self) -> &str {
+        self.config.base_url.as_deref().unwrap_or("https://api.anthropic.com/v1")
+    }
+    
+    fn api_key(&self) -> &str {
+        self.config.api_key.as_deref().unwrap_or_default()
+    }
+    
+    fn prompt_caching(&self) -> bool {
+        self.config.prompt_caching.unwrap_or(true)
+    }
+    
+    fn system_prompt(messages: &[ChatMessage]) -> Option<String> {
+        messages.iter()
+            .find(|m| m.role == Role::System)
+            .map(|m| m.text())
+    }
+    
+    // Anthropic wants tool calls as tool_use blocks on the assistant turn and their results
+    // as tool_result blocks in the user turn that follows
+    fn wire_messages(messages: &[ChatMessage]) -> Vec<serde_json::Value> {
+        let mut conversation_messages: Vec<serde_json::Value> = Vec::new();
+        
+        for m in messages.iter().filter(|m| m.role != Role::System) {
+            let role = match m.role {
+                Role::Assistant => "assistant",
+                _ => "user",
+            };
+            
+            let blocks: Vec<_> = m.content.iter().filter_map(|part| match part {
+                ContentPart::Text { text } => Some(json!({ "type": "text", "text": text })),
+                ContentPart::ToolCall(call) => Some(json!({
+                    "type": "tool_use",
+                    "id": call.id,
+                    "name": call.name,
+                    "input": call.arguments
+                })),
+                ContentPart::ToolResult { tool_call_id, result, .. } => Some(json!({
+                    "type": "tool_result",
+                    "tool_use_id": tool_call_id,
+                    "content": result.to_message_content(),
+                    "is_error": !result.success
+                })),
+                ContentPart::Image { media_type, data } => Some(json!({
+                    "type": "image",
+                    "source": { "type": "base64", "media_type": media_type, "data": data }
+                })),
+                // Thinking can only be replayed with the signature Anthropic issued for it. It
+                // has to be, for the assistant turn that called the tools being answered.
+                ContentPart::Thinking { text, redacted: true, .. } => Some(json!({
+                    "type": "redacted_thinking",
+                    "data": text
+                })),
+                ContentPart::Thinking { text, signature: Some(signature), .. } if !text.is_empty() => Some(json!({
+                    "type": "thinking",
+                    "thinking": text,
+                    "signature": signature
+                })),
+                ContentPart::Thinking { .. } => None,
+            }).collect();
+            
+            // Roles must alternate, so consecutive turns from the same side are merged
+            match conversation_messages.last_mut() {
+                Some(last) if last["role"] == role => {
+                    if let Some(existing) = last["content"].as_array_mut() {
+                        existing.extend(blocks);
+                    }
+                }
+                _ => conversation_messages.push(json!({ "role": role, "content": blocks })),
+            }
+        }
+        
+        conversation_messages
+    }
+    
+    // Marks the last block of each of the last user turns as a cache breakpoint. The prefix up
+    // to the newest one is written to the cache, and the one before it reads back what the
+    // previous request wrote, even after a turn with several tool calls.
+    fn add_cache_breakpoints(conversation_messages: &mut [serde_json::Value]) {
+        let user_turns = conversation_messages.iter_mut()
+            .rev()
+            .filter(|m| m["role"] == "user")
+            .take(CACHED_TURNS);
+        for turn in user_turns {
+            // Empty text blocks cannot be cached
+            let last_block = turn["content"].as_array_mut()
+                .and_then(|blocks| blocks.last_mut())
+                .filter(|block| block["type"] != "text" || block["text"] != "");
+            if let Some(block) = last_block {
+                block["cache_control"] = json!({ "type": "ephemeral" });
+            }
+        }
+    }
+    
+    async fn post(&self, messages: &[ChatMessage], tools: &[ToolDefinition], stream: bool) -> Result<reqwest::Response> {
+        let base_url = self.base_url();
+        let model = self.config.model.as_deref().unwrap_or("claude-3-sonnet-20240229");
+        
+        // Convert messages to Anthropic format
+        let system_message = Self::system_prompt(messages);
+        
+        let mut conversation_messages = Self::wire_messages(messages);
+        let caching = self.prompt_caching();
+        if caching {
+            Self::add_cache_breakpoints(&mut conversation_messages);
+        }
+        
+        let params = &self.config.parameters;
+        let mut request_body = json!({
+            "model": model,
+            "messages": conversation_messages,
+            // max_tokens is required by the Messages API, and includes the thinking budget
+            "max_tokens": params.max_tokens
+                .unwrap_or(params.thinking_budget.unwrap_or_default() + DEFAULT_MAX_TOKENS)
+        });
+        
+        if let Some(budget) = params.thinking_budget {
+            request_body["thinking"] = json!({ "type": "enabled", "budget_tokens": budget });
+        }
+        
+        if let Some(temperature) = params.temperature {
+            request_body["temperature"] = json!(temperature);
+        }
+        if let Some(top_p) = params.top_p {
+            request_body["top_p"] = json!(top_p);
+        }
+        if !params.stop.is_empty() {
+            request_body["stop_sequences"] = json!(params.stop);
+        }
+        
+        if let Some(system) = system_message.filter(|system| !system.is_empty()) {
+            request_body["system"] = if caching {
+                json!([{ "type": "text", "text": system, "cache_control": { "type": "ephemeral" } }])
+            } else {
+                json!(system)
+            };
+        }
+        
+        if !tools.is_empty() {
+            let mut definitions: Vec<_> = tools.iter().map(|tool| json!({
+                "name": tool.name,
+                "description": tool.description,
+                "input_schema": tool.parameters
+            })).collect();
+            // Tools come before the system prompt in the cached prefix
+            if let (true, Some(last)) = (caching, definitions.last_mut()) {
+                last["cache_control"] = json!({ "type": "ephemeral" });
+            }
+            request_body["tools"] = json!(definitions);
+        }
+        
+        if stream {
+            request_body["stream"] = json!(true);
+        }
+        
+        let request = self.client
+            .post(format!("{}/messages", base_url))
+            .header("x-api-key", self.api_key())
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .header("Content-Type", "application/json")
+            .json(&request_body);
+        Ok(retry::send(request, &self.retry_settings()).await?)
+    }
+    
+    // input_tokens only counts the part of the prompt that was not read from or written to the cache
+    fn parse_usage(u: &serde_json::Value) -> Option<Usage> {
+        let cache_read = u["cache_read_input_tokens"].as_u64().unwrap_or_default();
+        let cache_write = u["cache_creation_input_tokens"].as_u64().unwrap_or_default();
+        let prompt = u["input_tokens"].as_u64()? + cache_read + cache_write;
+        let completion = u["output_tokens"].as_u64().unwrap_or_default();
+        
+        Some(Usage {
+            prompt_tokens: prompt as u32,
+            completion_tokens: completion as u32,
+            total_tokens: (prompt + completion) as u32,
+            cached_tokens: cache_read as u32,
+            cache_write_tokens: cache_write as u32,
+        })
+    }
+    
+    fn parse_block(block: &serde_json::Value) -> Result<Option<ContentPart>> {
+        Ok(match block["type"].as_str() {
+            Some("text") => Some(ContentPart::text(block["text"].as_str().unwrap_or_default())),
+            Some("thinking") => Some(ContentPart::Thinking {
+                text: block["thinking"].as_str().unwrap_or_default().to_string(),
+                signature: block["signature"].as_str().filter(|s| !s.is_empty()).map(str::to_string),
+                redacted: false,
+            }),
+            Some("redacted_thinking") => Some(ContentPart::Thinking {
+                text: block["data"].as_str().unwrap_or_default().to_string(),
+                signature: None,
+                redacted: true,
+            }),
+            Some("tool_use") => Some(ContentPart::ToolCall(ToolCall {
+                id: block["id"].as_str()
+                    .ok_or_else(|| LlmError::invalid_response("tool_use block without an id", block.to_string()))?
+                    .to_string(),
+                name: block["name"].as_str()
+                    .ok_or_else(|| LlmError::invalid_response("tool_use block without a name", block.to_string()))?
+                    .to_string(),
+                arguments: serde_json::from_value(block["input"].clone()).unwrap_or_default(),
+                description: None,
+            })),
+            _ => None,
+        })
+    }
+}
+
+#[async_trait]
+impl Provider for AnthropicProvider {
+    async fn send(&self, messages: &[ChatMessage], tools: &[ToolDefinition]) -> Result<ChatResponse> {
+        let response = self.post(messages, tools, false).await?;
+        let response_body = read_json(response).await?;
+        
+        let blocks = response_body["content"]
+            .as_array()
+            .ok_or_else(|| LlmError::invalid_response("no content blocks in response", response_body.to_string()))?;
+        
+        let content = blocks.iter()
+            .map(Self::parse_block)
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .flatten()
+            .collect();
+        
+        let usage = Self::parse_usage(&response_body["usage"]);
+        
+        Ok(ChatResponse { content, usage })
+    }
+    
+    async fn stream(&self, messages: &[ChatMessage], tools: &[ToolDefinition], on_event: &mut EventHandler<'_>) -> Result<ChatResponse> {
+        let response = self.post(messages, tools, true).await?;
+        
+        // Content blocks are rebuilt in their final JSON shape; tool input arrives as
+        // partial JSON text that is only parsed once the block is complete
+        let mut blocks: BTreeMap<u64, serde_json::Value> = BTreeMap::new();
+        let mut partial_inputs: BTreeMap<u64, String> = BTreeMap::new();
+        // Starts with message_start's usage; message_delta updates the counts
+        let mut usage = json!({});
+        
+        let mut parser = SseParser::new();
+        let mut body = response.bytes_stream();
+        'stream: while let Some(chunk) = body.next().await {
+            for event in parser.feed(&chunk.map_err(LlmError::from)?) {
+                let data: serde_json::Value = serde_json::from_str(&event.data)
+                    .map_err(|e| LlmError::invalid_response(format!("invalid stream event: {}", e), event.data.as_str()))?;
+                let index = data["index"].as_u64().unwrap_or_default();
+                
+                match data["type"].as_str().unwrap_or_default() {
+                    "message_start" => {
+                        usage = data["message"]["usage"].clone();
+                    }
+                    "content_block_start" => {
+                        blocks.insert(index, data["content_block"].clone());
+                    }
+                    "content_block_delta" => {
+                        let delta = &data["delta"];
+                        let Some(block) = blocks.get_mut(&index) else { continue };
+                        match delta["type"].as_str() {
+                            Some("text_delta") => {
+                                let fragment = delta["text"].as_str().unwrap_or_default();
+                                let joined = format!("{}{}", block["text"].as_str().unwrap_or_default(), fragment);
+                                block["text"] = json!(joined);
+                                on_event(StreamEvent::Text(fragment));
+                            }
+                            Some("thinking_delta") => {
+                                let fragment = delta["thinking"].as_str().unwrap_or_default();
+                                let joined = format!("{}{}", block["thinking"].as_str().unwrap_or_default(), fragment);
+                                block["thinking"] = json!(joined);
+                                on_event(StreamEvent::Thinking(fragment));
+                            }
+                            Some("signature_delta") => {
+                                let joined = format!(
+                                    "{}{}",
+                                    block["signature"].as_str().unwrap_or_default(),
+                                    delta["signature"].as_str().unwrap_or_default()
+                                );
+                                block["signature"] = json!(joined);
+                            }
+                            Some("input_json_delta") => {
+                                partial_inputs.entry(index).or_default()
+                                    .push_str(delta["partial_json"].as_str().unwrap_or_default());
+                            }
+                            _ => {}
+                        }
+                    }
+                    "content_block_stop" => {
+                        if let (Some(block), Some(input)) = (blocks.get_mut(&index), partial_inputs.remove(&index)) {
+                            if !input.trim().is_empty() {
+                                block["input"] = serde_json::from_str(&input)
+                                    .map_err(|e| LlmError::invalid_response(format!("invalid tool input in stream: {}", e), input.as_str()))?;
+                            }
+                        }
+                    }
+                    "message_delta" => {
+                        for (key, count) in data["usage"].as_object().into_iter().flatten() {
+                            if !count.is_null() {
+                                usage[key] = count.clone();
+                            }
+                        }
+                    }
+                    "message_stop" => break 'stream,
+                    "error" => {
+                        // e.g. overloaded_error after the response already started
+                        return Err(LlmError::from_body(None, event.data).into());
+                    }
+                    _ => {}
+                }
+            }
+        }
+        
+        let content = blocks.values()
+            .map(Self::parse_block)
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .flatten()
+            .collect();
+        
+        let usage = Self::parse_usage(&usage);
+        
+        Ok(ChatResponse { content, usage })
+    }
+    
+    async fn list_models(&self) -> Result<Vec<String>> {
+        let request = self.client
+            .get(format!("{}/models", self.base_url()))
+            .query(&[("limit", "1000")])
+            .header("x-api-key", self.api_key())
+            .header("anthropic-version", ANTHROPIC_VERSION);
+        let response = retry::send(request, &self.retry_settings()).await?;
+        let body = read_json(response).await?;
+        
+        let mut models: Vec<String> = body["data"].as_array()
+            .ok_or_else(|| LlmError::invalid_response("no data array in model list", body.to_string()))?
+            .iter()
+            .filter_map(|model| model["id"].as_str().map(|id| id.to_string()))
+            .collect();
+        models.sort();
+        
+        Ok(models)
+    }
+    
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            tools: true,
+            streaming: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SamplingParams;
+    use wiremock::matchers::{body_partial_json, method, path};
+    use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+    
+    fn provider(server: &MockServer, prompt_caching: Option<bool>) -> Box<dyn Provider> {
+        let config = ProviderConfig {
+            api_key: Some("test-key".to_string()),
+            base_url: Some(server.uri()),
+            model: Some("claude-test".to_string()),
+            retry: Some(RetrySettings { max_attempts: 1, ..Default::default() }),
+            prompt_caching,
+            ..Default::default()
+        };
+        AnthropicProvider::create(&config, Client::new()).unwrap()
+    }
+    
+    fn conversation() -> Vec<ChatMessage> {
+        vec![
+            ChatMessage::system("Be brief"),
+            ChatMessage::user("First question"),
+            ChatMessage::assistant(vec![ContentPart::text("First answer")]),
+            ChatMessage::user("Second question"),
+            ChatMessage::assistant(vec![ContentPart::text("Second answer")]),
+            ChatMessage::user("Third question"),
+        ]
+    }
+    
+    fn tools() -> Vec<ToolDefinition> {
+        vec![
+            ToolDefinition::new("read_file", "Read a file", json!({ "type": "object", "properties": {} })),
+            ToolDefinition::new("git_status", "Show git status", json!({ "type": "object", "properties": {} })),
+        ]
+    }
+    
+    fn reply() -> ResponseTemplate {
+        ResponseTemplate::new(200).set_body_json(json!({
+            "content": [{ "type": "text", "text": "Third answer" }],
+            "usage": {
+                "input_tokens": 12,
+                "cache_read_input_tokens": 2000,
+                "cache_creation_input_tokens": 300,
+                "output_tokens": 5
+            }
+        }))
+    }
+    
+    fn breakpoints(request: &Request) -> usize {
+        String::from_utf8_lossy(&request.body).matches("cache_control").count()
+    }
+    
+    #[tokio::test]
+    async fn caches_the_stable_prefix_and_reports_cache_usage() {
+        let server = MockServer::start().await;
+        let cached = json!({ "type": "ephemeral" });
+        Mock::given(method("POST"))
+            .and(path("/messages"))
+            .and(body_partial_json(json!({
+                "system": [{ "type": "text", "text": "Be brief", "cache_control": cached }],
+                "tools": [{ "name": "read_file" }, { "name": "git_status", "cache_control": cached }],
+                "messages": [
+                    { "role": "user", "content": [{ "text": "First question" }] },
+                    { "role": "assistant", "content": [{ "text": "First answer" }] },
+                    { "role": "user", "content": [{ "text": "Second question", "cache_control": cached }] },
+                    { "role": "assistant", "content": [{ "text": "Second answer" }] },
+                    { "role": "user", "content": [{ "text": "Third question", "cache_control": cached }] }
+                ]
+            })))
+            .respond_with(reply())
+            .mount(&server)
+            .await;
+        
+        let response = provider(&server, None).send(&conversation(), &tools()).await.unwrap();
+        let usage = response.usage.unwrap();
+        assert_eq!(usage.prompt_tokens, 2312);
+        assert_eq!(usage.cached_tokens, 2000);
+        assert_eq!(usage.cache_write_tokens, 300);
+        
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(breakpoints(&requests[0]), 4);
+    }
+    
+    #[tokio::test]
+    async fn streams_thinking_and_sends_it_back_with_tool_results() {
+        let server = MockServer::start().await;
+        let events = [
+            json!({ "type": "message_start", "message": { "usage": { "input_tokens": 20, "output_tokens": 1 } } }),
+            json!({ "type": "content_block_start", "index": 0, "content_block": { "type": "thinking", "thinking": "", "signature": "" } }),
+            json!({ "type": "content_block_delta", "index": 0, "delta": { "type": "thinking_delta", "thinking": "Check the " } }),
+            json!({ "type": "content_block_delta", "index": 0, "delta": { "type": "thinking_delta", "thinking": "status" } }),
+            json!({ "type": "content_block_delta", "index": 0, "delta": { "type": "signature_delta", "signature": "c2ln" } }),
+            json!({ "type": "content_block_stop", "index": 0 }),
+            json!({ "type": "content_block_start", "index": 1, "content_block": { "type": "tool_use", "id": "toolu_1", "name": "git_status", "input": {} } }),
+            json!({ "type": "content_block_stop", "index": 1 }),
+            json!({ "type": "message_delta", "usage": { "output_tokens": 30 } }),
+            json!({ "type": "message_stop" }),
+        ];
+        let body: String = events.iter().map(|event| format!("data: {}\n\n", event)).collect();
+        Mock::given(method("POST"))
+            .and(path("/messages"))
+            .and(body_partial_json(json!({
+                "thinking": { "type": "enabled", "budget_tokens": 2048 },
+                "max_tokens": 2048 + DEFAULT_MAX_TOKENS
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(body, "text/event-stream"))
+            .mount(&server)
+            .await;
+        
+        let config = ProviderConfig {
+            api_key: Some("test-key".to_string()),
+            base_url: Some(server.uri()),
+            parameters: SamplingParams { thinking_budget: Some(2048), ..Default::default() },
+            ..Default::default()
+        };
+        let provider = AnthropicProvider::create(&config, Client::new()).unwrap();
+        let mut thought = String::new();
+        let mut on_event = |event: StreamEvent| {
+            if let StreamEvent::Thinking(text) = event {
+                thought.push_str(text);
+            }
+        };
+        let response = provider.stream(&[ChatMessage::user("Status?")], &[], &mut on_event).await.unwrap();
+        assert_eq!(thought, "Check the status");
+        
+        // The signed thinking has to lead the assistant turn when the tool results go back
+        let wire = AnthropicProvider::wire_messages(&[ChatMessage::assistant(response.content)]);
+        assert_eq!(wire[0]["content"][0], json!({ "type": "thinking", "thinking": "Check the status", "signature": "c2ln" }));
+        assert_eq!(wire[0]["content"][1]["type"], "tool_use");
+    }
+    
+    #[test]
+    fn thinking_needs_room_and_default_sampling() {
+        let config = |parameters| ProviderConfig {
+            api_key: Some("test-key".to_string()),
+            parameters,
+            ..Default::default()
+        };
+        let budget = |budget| SamplingParams { thinking_budget: Some(budget), ..Default::default() };
+        
+        assert!(AnthropicProvider::create(&config(budget(512)), Client::new()).is_err());
+        assert!(AnthropicProvider::create(&config(SamplingParams { max_tokens: Some(2000), ..budget(2000) }), Client::new()).is_err());
+        assert!(AnthropicProvider::create(&config(SamplingParams { temperature: Some(0.5), ..budget(2000) }), Client::new()).is_err());
+        assert!(AnthropicProvider::create(&config(SamplingParams { max_tokens: Some(8000), ..budget(2000) }), Client::new()).is_ok());
+    }
+    
+    #[tokio::test]
+    async fn sends_no_breakpoints_when_caching_is_off() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/messages"))
+            .and(body_partial_json(json!({ "system": "Be brief" })))
+            .respond_with(reply())
+            .mount(&server)
+            .await;
+        
+        provider(&server, Some(false)).send(&conversation(), &tools()).await.unwrap();
+        
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(breakpoints(&requests[0]), 0);
+    }
+    
+    #[tokio::test]
+    async fn lists_models_in_order() {
+        let server = MockServer::start().await;
+        // The API lists the newest models first
+        Mock::given(method("GET"))
+            .and(path("/models"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "data": [
+                    { "type": "model", "id": "claude-sonnet-4-20250514" },
+                    { "type": "model", "id": "claude-3-5-haiku-20241022" },
+                    { "type": "model", "id": "claude-3-opus-20240229" }
+                ],
+                "has_more": false
+            })))
+            .mount(&server)
+            .await;
+        
+        let models = provider(&server, None).list_models().await.unwrap();
+        assert_eq!(models, ["claude-3-5-haiku-20241022", "claude-3-opus-20240229", "claude-sonnet-4-20250514"]);
+    }
+}
@@ -0,0 +1,311 @@
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use reqwest::header::{HeaderValue, CONTENT_TYPE};
+use reqwest::{RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use super::{Capabilities, Provider};
+use crate::config::{CassetteMode, CassetteSettings};
+use crate::error::LlmError;
+use crate::llm::{ChatResponse, EventHandler};
+use crate::message::{ChatMessage, ContentPart, Role};
+use crate::tools::ToolDefinition;
+
+// Provider calls saved by a recording, replayed later without a network
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Cassette {
+    interactions: Vec<Interaction>,
+}
+
+// One call to the provider and the HTTP exchanges it took, retries included
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Interaction {
+    request: RecordedRequest,
+    exchanges: Vec<Exchange>,
+}
+
+// What went over the wire. Headers are left out so API keys don't end up in the file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Exchange {
+    method: String,
+    path: String,
+    #[serde(default, skip_serializing_if = "serde_json::Value::is_null")]
+    request_body: serde_json::Value,
+    status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content_type: Option<String>,
+    response_body: String,
+}
+
+// What a call is matched on when replaying
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordedRequest {
+    messages: Vec<RecordedMessage>,
+    tools: Vec<String>,
+    stream: bool,
+}
+
+// A message without the timestamp and metadata, and without tool output, all of which
+// change from run to run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct RecordedMessage {
+    role: Role,
+    content: Vec<serde_json::Value>,
+}
+
+impl RecordedRequest {
+    fn new(messages: &[ChatMessage], tools: &[ToolDefinition], stream: bool) -> Self {
+        Self {
+            messages: messages.iter()
+                .map(|message| RecordedMessage {
+                    role: message.role,
+                    content: message.content.iter().map(Self::shape).collect(),
+                })
+                .collect(),
+            tools: tools.iter().map(|tool| tool.name.clone()).collect(),
+            stream,
+        }
+    }
+
+    // Tool results keep which call they answer and whether it succeeded. Their output
+    // (timestamps, directory listings, mtimes) need not repeat for a recording to replay.
+    fn shape(part: &ContentPart) -> serde_json::Value {
+        let mut value = serde_json::to_value(part).unwrap_or_default();
+        if let (ContentPart::ToolResult { .. }, Some(fields)) = (part, value.as_object_mut()) {
+            fields.remove("output");
+            fields.remove("error");
+        }
+        value
+    }
+
+    // Requests match on the conversation alone, so editing the system prompt or adding a
+    // tool does not invalidate existing recordings
+    fn matches(&self, other: &RecordedRequest) -> bool {
+        let conversation = |request: &RecordedRequest| {
+            request.messages.iter()
+                .filter(|message| message.role != Role::System)
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        self.stream == other.stream && conversation(self) == conversation(other)
+    }
+}
+
+impl Exchange {
+    fn response(self) -> Response {
+        let mut response = http::Response::new(reqwest::Body::from(self.response_body));
+        *response.status_mut() = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        if let Some(content_type) = self.content_type.and_then(|value| HeaderValue::from_str(&value).ok()) {
+            response.headers_mut().insert(CONTENT_TYPE, content_type);
+        }
+        Response::from(response)
+    }
+}
+
+// Exchanges sent so far, with the response bytes read from each
+type Recording = Arc<Mutex<Vec<(Exchange, Vec<u8>)>>>;
+
+// The HTTP exchanges of the provider call in progress, when it goes through a cassette
+#[derive(Debug, Clone)]
+enum Tape {
+    Record(Recording),
+    Replay(Arc<Mutex<std::vec::IntoIter<Exchange>>>),
+}
+
+tokio::task_local! {
+    static TAPE: Tape;
+}
+
+// Sends one HTTP request for a provider. Inside a cassette's call it is answered from the
+// recording, or sent and recorded, so the provider's own request and response handling
+// runs either way.
+pub async fn send_http(request: RequestBuilder) -> Result<Response, LlmError> {
+    let Ok(tape) = TAPE.try_with(Tape::clone) else {
+        return Ok(request.send().await?);
+    };
+
+    let (client, request) = request.build_split();
+    let request = request?;
+    let recording = match tape {
+        Tape::Replay(exchanges) => {
+            let exchange = exchanges.lock().unwrap().next().ok_or_else(|| LlmError::invalid_response(
+                format!("the cassette has no more responses for {} {}", request.method(), request.url().path()),
+                "",
+            ))?;
+            return Ok(exchange.response());
+        }
+        Tape::Record(recording) => recording,
+    };
+
+    let request_body = request.body()
+        .and_then(|body| body.as_bytes())
+        .map(|bytes| serde_json::from_slice(bytes)
+            .unwrap_or_else(|_| serde_json::Value::String(String::from_utf8_lossy(bytes).into_owned())))
+        .unwrap_or_default();
+    let method = request.method().to_string();
+    let path = request.url().path().to_string();
+
+    let response = client.execute(request).await?;
+    let status = response.status();
+    let headers = response.headers().clone();
+    let index = {
+        let mut recording = recording.lock().unwrap();
+        recording.push((Exchange {
+            method,
+            path,
+            request_body,
+            status: status.as_u16(),
+            content_type: headers.get(CONTENT_TYPE).and_then(|value| value.to_str().ok()).map(str::to_string),
+            response_body: String::new(),
+        }, Vec::new()));
+        recording.len() - 1
+    };
+
+    // The body is copied as the provider reads it, so streaming still shows text as it arrives
+    let body = response.bytes_stream().map(move |chunk| {
+        if let Ok(bytes) = &chunk {
+            recording.lock().unwrap()[index].1.extend_from_slice(bytes);
+        }
+        chunk
+    });
+    let mut response = http::Response::new(reqwest::Body::wrap_stream(body));
+    *response.status_mut() = status;
+    *response.headers_mut() = headers;
+    Ok(Response::from(response))
+}
+
+#[derive(Debug)]
+enum Mode {
+    // Sends every call and saves its exchanges
+    Record,
+    // Answers from the file; each recorded interaction is used at most once
+    Replay(Mutex<Vec<bool>>),
+}
+
+// Wraps a provider and records or replays its HTTP traffic
+#[derive(Debug)]
+pub struct CassetteProvider {
+    path: PathBuf,
+    cassette: Mutex<Cassette>,
+    inner: Box<dyn Provider>,
+    mode: Mode,
+}
+
+impl CassetteProvider {
+    pub fn create(settings: &CassetteSettings, inner: impl FnOnce() -> Result<Box<dyn Provider>>) -> Result<Box<dyn Provider>> {
+        let cassette = match settings.mode {
+            CassetteMode::Replay => Self::load(&settings.path)?,
+            // Recordings add to the file, so several runs can go into one cassette
+            CassetteMode::Record if settings.path.exists() => Self::load(&settings.path)?,
+            CassetteMode::Record => Cassette::default(),
+        };
+        let mode = match settings.mode {
+            CassetteMode::Replay => Mode::Replay(Mutex::new(vec![false; cassette.interactions.len()])),
+            CassetteMode::Record => Mode::Record,
+        };
+
+        Ok(Box::new(Self {
+            path: settings.path.clone(),
+            cassette: Mutex::new(cassette),
+            inner: inner()?,
+            mode,
+        }))
+    }
+
+    fn load(path: &Path) -> Result<Cassette> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read cassette {}: {}", path.display(), e))?;
+        serde_json::from_str(&content)
+            .map_err(|e| anyhow!("Invalid cassette {}: {}", path.display(), e))
+    }
+
+    // Runs one call of the wrapped provider against the tape
+    async fn call<T>(&self, request: RecordedRequest, call: impl Future<Output = Result<T>>) -> Result<T> {
+        match &self.mode {
+            Mode::Replay(used) => {
+                let exchanges = self.replay(used, &request)?;
+                TAPE.scope(Tape::Replay(Arc::new(Mutex::new(exchanges.into_iter()))), call).await
+            }
+            Mode::Record => {
+                let recording = Recording::default();
+                let result = TAPE.scope(Tape::Record(recording.clone()), call).await;
+
+                // Failed calls are kept too, so replaying them fails the same way
+                let exchanges: Vec<Exchange> = std::mem::take(&mut *recording.lock().unwrap())
+                    .into_iter()
+                    .map(|(mut exchange, body)| {
+                        exchange.response_body = String::from_utf8_lossy(&body).into_owned();
+                        exchange
+                    })
+                    .collect();
+                if !exchanges.is_empty() {
+                    self.record(request, exchanges).await?;
+                }
+                result
+            }
+        }
+    }
+
+    fn replay(&self, used: &Mutex<Vec<bool>>, request: &RecordedRequest) -> Result<Vec<Exchange>> {
+        let cassette = self.cassette.lock().unwrap();
+        let mut used = used.lock().unwrap();
+
+        let found = cassette.interactions.iter()
+            .enumerate()
+            .find(|(i, interaction)| !used[*i] && interaction.request.matches(request));
+        match found {
+            Some((i, interaction)) => {
+                used[i] = true;
+                Ok(interaction.exchanges.clone())
+            }
+            None => Err(anyhow!(
+                "No recorded response in {} matches this request ({} messages). \
+                Record the cassette again with \"mode\": \"record\".",
+                self.path.display(),
+                request.messages.len()
+            )),
+        }
+    }
+
+    async fn record(&self, request: RecordedRequest, exchanges: Vec<Exchange>) -> Result<()> {
+        let content = {
+            let mut cassette = self.cassette.lock().unwrap();
+            cassette.interactions.push(Interaction { request, exchanges });
+            serde_json::to_string_pretty(&*cassette)?
+        };
+
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&self.path, content).await
+            .map_err(|e| anyhow!("Failed to write cassette {}: {}", self.path.display(), e))
+    }
+}
+
+#[async_trait]
+impl Provider for CassetteProvider {
+    async fn send(&self, messages: &[ChatMessage], tools: &[ToolDefinition]) -> Result<ChatResponse> {
+        let request = RecordedRequest::new(messages, tools, false);
+        self.call(request, self.inner.send(messages, tools)).await
+    }
+
+    async fn stream(&self, messages: &[ChatMessage], tools: &[ToolDefinition], on_event: &mut EventHandler<'_>) -> Result<ChatResponse> {
+        let request = RecordedRequest::new(messages, tools, true);
+        self.call(request, self.inner.stream(messages, tools, on_event)).await
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>> {
+        match &self.mode {
+            Mode::Replay(_) => Ok(Vec::new()),
+            Mode::Record => self.inner.list_models().await,
+        }
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+}
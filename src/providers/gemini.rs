@@ -0,0 +1,560 @@
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use reqwest::Client;
+use serde_json::json;
+
+use super::{check_range, read_json, Capabilities, Provider};
+use crate::config::ProviderConfig;
+use crate::llm::{ChatResponse, EventHandler, StreamEvent, Usage};
+use crate::message::{ChatMessage, ContentPart, Role};
+use crate::error::LlmError;
+use crate::retry::{self, RetrySettings};
+use crate::sse::SseParser;
+use crate::tools::{ToolCall, ToolDefinition};
+
+// Google Gemini generateContent API
+#[derive(Debug)]
+pub struct GeminiProvider {
+    config: ProviderConfig,
+    client: Client,
+}
+
+impl GeminiProvider {
+    pub fn create(config: &ProviderConfig, client: Client) -> Result<Box<dyn Provider>> {
+        if config.api_key.is_none() {
+            return Err(anyhow!("The Gemini API requires an api_key"));
+        }
+        
+        let params = &config.parameters;
+        check_range("temperature", params.temperature, 0.0, 2.0)?;
+        check_range("top_p", params.top_p, 0.0, 1.0)?;
+        if params.max_tokens == Some(0) {
+            return Err(anyhow!("max_tokens must be greater than 0"));
+        }
+        if params.stop.len() > 5 {
+            return Err(anyhow!("At most 5 stop sequences are supported, got {}", params.stop.len()));
+        }
+        if params.reasoning_effort.is_some() {
+            return Err(anyhow!("reasoning_effort is not supported by the Gemini API; set thinking_budget instead"));
+        }
+        
+        Ok(Box::new(Self {
+            config: config.clone(),
+            client,
+        }))
+    }
+    
+    fn retry_settings(&self) -> RetrySettings {
+        self.config.retry.clone().unwrap_or_default()
+    }
+    
+    fn base_url(&self) -> &str {
+        self.config.base_url.as_deref().unwrap_or("https://generativelanguage.googleapis.com/v1beta")
+    }
+    
+    fn api_key(&self) -> &str {
+        self.config.api_key.as_deref().unwrap_or_default()
+    }
+    
+    fn system_instruction(messages: &[ChatMessage]) -> Option<serde_json::Value> {
+        let text = messages.iter()
+            .filter(|m| m.role == Role::System)
+            .map(|m| m.text())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        (!text.is_empty()).then(|| json!({ "parts": [{ "text": text }] }))
+    }
+    
+    // Gemini calls the assistant "model". Function calls go out as functionCall parts on the
+    // model turn and their results as functionResponse parts on the user turn that follows.
+    fn wire_contents(messages: &[ChatMessage]) -> Vec<serde_json::Value> {
+        let mut contents: Vec<serde_json::Value> = Vec::new();
+        
+        for m in messages.iter().filter(|m| m.role != Role::System) {
+            let role = match m.role {
+                Role::Assistant => "model",
+                _ => "user",
+            };
+            
+            // A thought signature belongs on the part that followed the thinking it signs
+            let mut signature = None;
+            let mut parts = Vec::new();
+            for part in &m.content {
+                let mut wire = match part {
+                    ContentPart::Text { text } if text.is_empty() => continue,
+                    ContentPart::Text { text } => json!({ "text": text }),
+                    ContentPart::ToolCall(call) => json!({
+                        "functionCall": { "name": call.name, "args": call.arguments }
+                    }),
+                    ContentPart::ToolResult { name, result, .. } => json!({
+                        "functionResponse": {
+                            "name": name,
+                            // The response has to be an object
+                            "response": if result.success {
+                                json!({ "output": result.to_message_content() })
+                            } else {
+                                json!({ "error": result.to_message_content() })
+                            }
+                        }
+                    }),
+                    ContentPart::Image { media_type, data } => json!({
+                        "inlineData": { "mimeType": media_type, "data": data }
+                    }),
+                    ContentPart::Thinking { signature: Some(signed), redacted: false, .. } => {
+                        signature = Some(signed);
+                        continue;
+                    }
+                    ContentPart::Thinking { .. } => continue,
+                };
+                if let Some(signed) = signature.take() {
+                    wire["thoughtSignature"] = json!(signed);
+                }
+                parts.push(wire);
+            }
+            
+            if parts.is_empty() {
+                continue;
+            }
+            
+            // Turns must alternate, so consecutive turns from the same side are merged
+            match contents.last_mut() {
+                Some(last) if last["role"] == role => {
+                    if let Some(existing) = last["parts"].as_array_mut() {
+                        existing.extend(parts);
+                    }
+                }
+                _ => contents.push(json!({ "role": role, "parts": parts })),
+            }
+        }
+        
+        contents
+    }
+    
+    fn function_declarations(tools: &[ToolDefinition]) -> Vec<serde_json::Value> {
+        tools.iter().map(|tool| {
+            let mut declaration = json!({
+                "name": tool.name,
+                "description": tool.description
+            });
+            // Gemini rejects object schemas without properties, so tools that take no
+            // arguments are declared without parameters
+            let has_properties = tool.parameters["properties"].as_object().is_some_and(|p| !p.is_empty());
+            if has_properties {
+                declaration["parameters"] = tool.parameters.clone();
+            }
+            declaration
+        }).collect()
+    }
+    
+    fn generation_config(&self) -> serde_json::Value {
+        let params = &self.config.parameters;
+        let mut config = json!({});
+        
+        if let Some(temperature) = params.temperature {
+            config["temperature"] = json!(temperature);
+        }
+        if let Some(top_p) = params.top_p {
+            config["topP"] = json!(top_p);
+        }
+        if let Some(max_tokens) = params.max_tokens {
+            config["maxOutputTokens"] = json!(max_tokens);
+        }
+        if !params.stop.is_empty() {
+            config["stopSequences"] = json!(params.stop);
+        }
+        if let Some(seed) = params.seed {
+            config["seed"] = json!(seed);
+        }
+        if let Some(budget) = params.thinking_budget {
+            config["thinkingConfig"] = json!({ "thinkingBudget": budget, "includeThoughts": true });
+        }
+        
+        config
+    }
+    
+    async fn post(&self, messages: &[ChatMessage], tools: &[ToolDefinition], stream: bool) -> Result<reqwest::Response> {
+        let model = self.config.model.as_deref().unwrap_or("gemini-2.5-flash");
+        
+        let mut request_body = json!({
+            "contents": Self::wire_contents(messages)
+        });
+        
+        if let Some(system) = Self::system_instruction(messages) {
+            request_body["systemInstruction"] = system;
+        }
+        
+        let generation_config = self.generation_config();
+        if generation_config.as_object().is_some_and(|c| !c.is_empty()) {
+            request_body["generationConfig"] = generation_config;
+        }
+        
+        if !tools.is_empty() {
+            request_body["tools"] = json!([{ "functionDeclarations": Self::function_declarations(tools) }]);
+        }
+        
+        let url = if stream {
+            format!("{}/models/{}:streamGenerateContent?alt=sse", self.base_url(), model)
+        } else {
+            format!("{}/models/{}:generateContent", self.base_url(), model)
+        };
+        
+        let request = self.client
+            .post(url)
+            .header("x-goog-api-key", self.api_key())
+            .header("Content-Type", "application/json")
+            .json(&request_body);
+        Ok(retry::send(request, &self.retry_settings()).await?)
+    }
+    
+    // Thinking tokens are billed as output, so they count as completion tokens
+    fn parse_usage(u: &serde_json::Value) -> Option<Usage> {
+        let prompt = u["promptTokenCount"].as_u64()?;
+        let completion = u["candidatesTokenCount"].as_u64().unwrap_or_default()
+            + u["thoughtsTokenCount"].as_u64().unwrap_or_default();
+        
+        Some(Usage {
+            prompt_tokens: prompt as u32,
+            completion_tokens: completion as u32,
+            total_tokens: u["totalTokenCount"].as_u64().unwrap_or(prompt + completion) as u32,
+            cached_tokens: u["cachedContentTokenCount"].as_u64().unwrap_or_default() as u32,
+            cache_write_tokens: 0,
+        })
+    }
+    
+    // The parts of the first candidate, or an error when the prompt itself was blocked
+    fn candidate_parts(body: &serde_json::Value) -> Result<Vec<serde_json::Value>> {
+        if let Some(reason) = body["promptFeedback"]["blockReason"].as_str() {
+            return Err(LlmError::invalid_response(format!("the prompt was blocked ({})", reason), body.to_string()).into());
+        }
+        
+        Ok(body["candidates"][0]["content"]["parts"].as_array().cloned().unwrap_or_default())
+    }
+    
+    // A part signed with a thoughtSignature becomes a thinking part holding the signature,
+    // followed by the part itself
+    fn parse_part(part: &serde_json::Value) -> Result<Vec<ContentPart>> {
+        let signature = part["thoughtSignature"].as_str().map(str::to_string);
+        
+        // Thought summaries, only sent when thinking_budget is set
+        if part["thought"].as_bool() == Some(true) {
+            let text = part["text"].as_str().unwrap_or_default().to_string();
+            return Ok(vec![ContentPart::Thinking { text, signature, redacted: false }]);
+        }
+        
+        let mut parts = Vec::new();
+        if let Some(signature) = signature {
+            parts.push(ContentPart::Thinking { text: String::new(), signature: Some(signature), redacted: false });
+        }
+        parts.extend(Self::parse_answer_part(part)?);
+        Ok(parts)
+    }
+    
+    fn parse_answer_part(part: &serde_json::Value) -> Result<Option<ContentPart>> {
+        if let Some(text) = part["text"].as_str() {
+            return Ok(Some(ContentPart::text(text)).filter(|_| !text.is_empty()));
+        }
+        
+        let call = &part["functionCall"];
+        if call.is_object() {
+            let name = call["name"].as_str()
+                .ok_or_else(|| LlmError::invalid_response("functionCall without a name", part.to_string()))?;
+            return Ok(Some(ContentPart::ToolCall(ToolCall {
+                // Gemini matches results to calls by name; ids are only sent by some models
+                id: call["id"].as_str()
+                    .map(|id| id.to_string())
+                    .unwrap_or_else(|| format!("call_{}", uuid::Uuid::new_v4().simple())),
+                name: name.to_string(),
+                arguments: serde_json::from_value(call["args"].clone()).unwrap_or_default(),
+                description: None,
+            })));
+        }
+        
+        Ok(None)
+    }
+}
+
+#[async_trait]
+impl Provider for GeminiProvider {
+    async fn send(&self, messages: &[ChatMessage], tools: &[ToolDefinition]) -> Result<ChatResponse> {
+        let response = self.post(messages, tools, false).await?;
+        let response_body = read_json(response).await?;
+        
+        let content = Self::candidate_parts(&response_body)?
+            .iter()
+            .map(Self::parse_part)
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .flatten()
+            .collect();
+        
+        let usage = Self::parse_usage(&response_body["usageMetadata"]);
+        
+        Ok(ChatResponse { content, usage })
+    }
+    
+    async fn stream(&self, messages: &[ChatMessage], tools: &[ToolDefinition], on_event: &mut EventHandler<'_>) -> Result<ChatResponse> {
+        let response = self.post(messages, tools, true).await?;
+        
+        // Every chunk is a complete GenerateContentResponse holding the next few parts. Text
+        // and thought parts continue each other; function calls always arrive whole.
+        let mut content: Vec<ContentPart> = Vec::new();
+        let mut usage = None;
+        
+        let mut parser = SseParser::new();
+        let mut body = response.bytes_stream();
+        while let Some(chunk) = body.next().await {
+            for event in parser.feed(&chunk.map_err(LlmError::from)?) {
+                let chunk: serde_json::Value = serde_json::from_str(&event.data)
+                    .map_err(|e| LlmError::invalid_response(format!("invalid stream chunk: {}", e), event.data.as_str()))?;
+                
+                if chunk.get("error").is_some() {
+                    return Err(LlmError::from_body(None, event.data).into());
+                }
+                
+                // Each chunk reports the usage so far
+                if let Some(chunk_usage) = Self::parse_usage(&chunk["usageMetadata"]) {
+                    usage = Some(chunk_usage);
+                }
+                
+                for part in Self::candidate_parts(&chunk)? {
+                    for part in Self::parse_part(&part)? {
+                        match (content.last_mut(), part) {
+                            (Some(ContentPart::Text { text }), ContentPart::Text { text: fragment }) => {
+                                on_event(StreamEvent::Text(&fragment));
+                                text.push_str(&fragment);
+                            }
+                            (
+                                Some(ContentPart::Thinking { text, signature, .. }),
+                                ContentPart::Thinking { text: fragment, signature: signed, .. },
+                            ) => {
+                                if !fragment.is_empty() {
+                                    on_event(StreamEvent::Thinking(&fragment));
+                                }
+                                text.push_str(&fragment);
+                                if signed.is_some() {
+                                    *signature = signed;
+                                }
+                            }
+                            (_, part) => {
+                                match &part {
+                                    ContentPart::Text { text } => on_event(StreamEvent::Text(text)),
+                                    ContentPart::Thinking { text, .. } if !text.is_empty() => on_event(StreamEvent::Thinking(text)),
+                                    _ => {}
+                                }
+                                content.push(part);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        
+        Ok(ChatResponse { content, usage })
+    }
+    
+    async fn list_models(&self) -> Result<Vec<String>> {
+        let request = self.client
+            .get(format!("{}/models", self.base_url()))
+            .query(&[("pageSize", "1000")])
+            .header("x-goog-api-key", self.api_key());
+        let response = retry::send(request, &self.retry_settings()).await?;
+        let body = read_json(response).await?;
+        
+        // Names look like "models/gemini-2.5-flash"; embedding models cannot chat
+        let mut models: Vec<String> = body["models"].as_array()
+            .ok_or_else(|| LlmError::invalid_response("no models array in model list", body.to_string()))?
+            .iter()
+            .filter(|model| {
+                model["supportedGenerationMethods"].as_array()
+                    .is_some_and(|methods| methods.iter().any(|m| m == "generateContent"))
+            })
+            .filter_map(|model| model["name"].as_str())
+            .map(|name| name.trim_start_matches("models/").to_string())
+            .collect();
+        models.sort();
+        
+        Ok(models)
+    }
+    
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            tools: true,
+            streaming: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::ToolResult;
+    use wiremock::matchers::{body_partial_json, header, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+    
+    fn provider(server: &MockServer) -> Box<dyn Provider> {
+        let config = ProviderConfig {
+            api_key: Some("test-key".to_string()),
+            base_url: Some(server.uri()),
+            model: Some("gemini-test".to_string()),
+            retry: Some(RetrySettings { max_attempts: 1, ..Default::default() }),
+            ..Default::default()
+        };
+        GeminiProvider::create(&config, Client::new()).unwrap()
+    }
+    
+    fn conversation() -> Vec<ChatMessage> {
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            name: "read_file".to_string(),
+            arguments: [("path".to_string(), json!("Cargo.toml"))].into_iter().collect(),
+            description: None,
+        };
+        let result = ToolResult {
+            success: true,
+            output: "[package]".to_string(),
+            error: None,
+        };
+        vec![
+            ChatMessage::system("Be brief"),
+            ChatMessage::user("What is in Cargo.toml?"),
+            ChatMessage::assistant(vec![ContentPart::ToolCall(call.clone())]),
+            ChatMessage::tool_results(vec![(call, result)]),
+        ]
+    }
+    
+    fn tools() -> Vec<ToolDefinition> {
+        vec![
+            ToolDefinition::new("read_file", "Read a file", json!({
+                "type": "object",
+                "properties": { "path": { "type": "string" } },
+                "required": ["path"]
+            })),
+            ToolDefinition::new("git_status", "Show git status", json!({ "type": "object", "properties": {} })),
+        ]
+    }
+    
+    #[tokio::test]
+    async fn sends_history_and_tools_in_gemini_format() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/models/gemini-test:generateContent"))
+            .and(header("x-goog-api-key", "test-key"))
+            .and(body_partial_json(json!({
+                "systemInstruction": { "parts": [{ "text": "Be brief" }] },
+                "contents": [
+                    { "role": "user", "parts": [{ "text": "What is in Cargo.toml?" }] },
+                    { "role": "model", "parts": [{ "functionCall": { "name": "read_file", "args": { "path": "Cargo.toml" } } }] },
+                    { "role": "user", "parts": [{ "functionResponse": { "name": "read_file", "response": { "output": "[package]" } } }] }
+                ],
+                "tools": [{ "functionDeclarations": [
+                    { "name": "read_file", "parameters": { "required": ["path"] } },
+                    { "name": "git_status" }
+                ] }]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "candidates": [{ "content": { "role": "model", "parts": [
+                    { "text": "It is a Rust manifest. " },
+                    { "functionCall": { "name": "git_status", "args": {} } }
+                ] } }],
+                "usageMetadata": { "promptTokenCount": 40, "candidatesTokenCount": 8, "thoughtsTokenCount": 2, "totalTokenCount": 50, "cachedContentTokenCount": 16 }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        
+        let response = provider(&server).send(&conversation(), &tools()).await.unwrap();
+        
+        assert_eq!(response.text(), "It is a Rust manifest. ");
+        assert_eq!(response.tool_calls()[0].name, "git_status");
+        let usage = response.usage.unwrap();
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens, usage.total_tokens, usage.cached_tokens), (40, 10, 50, 16));
+        // The tool declared without parameters must not carry an empty schema
+        let request: serde_json::Value = server.received_requests().await.unwrap()[0].body_json().unwrap();
+        assert!(request["tools"][0]["functionDeclarations"][1].get("parameters").is_none());
+    }
+    
+    #[tokio::test]
+    async fn streams_text_thoughts_and_function_calls() {
+        let server = MockServer::start().await;
+        let chunks = [
+            json!({ "candidates": [{ "content": { "role": "model", "parts": [{ "text": "The user wants a.rs", "thought": true }] } }] }),
+            json!({ "candidates": [{ "content": { "role": "model", "parts": [{ "text": "Let me " }] } }] }),
+            json!({ "candidates": [{ "content": { "role": "model", "parts": [{ "text": "look." }] } }] }),
+            json!({
+                "candidates": [{ "content": { "role": "model", "parts": [{
+                    "functionCall": { "name": "read_file", "args": { "path": "a.rs" } },
+                    "thoughtSignature": "c2lnbmVk"
+                }] } }],
+                "usageMetadata": { "promptTokenCount": 12, "candidatesTokenCount": 5, "totalTokenCount": 17 }
+            }),
+        ];
+        let body: String = chunks.iter().map(|chunk| format!("data: {}\r\n\r\n", chunk)).collect();
+        Mock::given(method("POST"))
+            .and(path("/models/gemini-test:streamGenerateContent"))
+            .and(query_param("alt", "sse"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(body, "text/event-stream"))
+            .mount(&server)
+            .await;
+        
+        let mut streamed = String::new();
+        let mut thought = String::new();
+        let mut on_event = |event: StreamEvent| match event {
+            StreamEvent::Text(text) => streamed.push_str(text),
+            StreamEvent::Thinking(text) => thought.push_str(text),
+        };
+        let response = provider(&server).stream(&conversation(), &tools(), &mut on_event).await.unwrap();
+        
+        assert_eq!(streamed, "Let me look.");
+        assert_eq!(thought, "The user wants a.rs");
+        assert_eq!(response.thinking(), "The user wants a.rs");
+        assert_eq!(response.text(), "Let me look.");
+        assert_eq!(response.tool_calls()[0].arguments["path"], "a.rs");
+        assert_eq!(response.usage.clone().unwrap().total_tokens, 17);
+        
+        // The signature goes back on the call it came with; thought text is not sent
+        let contents = GeminiProvider::wire_contents(&[ChatMessage::assistant(response.content)]);
+        assert_eq!(contents[0]["parts"], json!([
+            { "text": "Let me look." },
+            { "functionCall": { "name": "read_file", "args": { "path": "a.rs" } }, "thoughtSignature": "c2lnbmVk" }
+        ]));
+    }
+    
+    #[tokio::test]
+    async fn classifies_gemini_errors() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(json!({
+                "error": {
+                    "code": 400,
+                    "message": "API key not valid. Please pass a valid API key.",
+                    "status": "INVALID_ARGUMENT",
+                    "details": [{ "@type": "type.googleapis.com/google.rpc.ErrorInfo", "reason": "API_KEY_INVALID" }]
+                }
+            })))
+            .mount(&server)
+            .await;
+        
+        let error = provider(&server).send(&conversation(), &[]).await.unwrap_err();
+        assert!(matches!(error.downcast_ref::<LlmError>(), Some(LlmError::Auth(_))));
+    }
+    
+    #[tokio::test]
+    async fn lists_chat_models() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/models"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "models": [
+                    { "name": "models/gemini-2.5-pro", "supportedGenerationMethods": ["generateContent", "countTokens"] },
+                    { "name": "models/text-embedding-004", "supportedGenerationMethods": ["embedContent"] },
+                    { "name": "models/gemini-2.5-flash", "supportedGenerationMethods": ["generateContent"] }
+                ]
+            })))
+            .mount(&server)
+            .await;
+        
+        let models = provider(&server).list_models().await.unwrap();
+        assert_eq!(models, ["gemini-2.5-flash", "gemini-2.5-pro"]);
+    }
+}
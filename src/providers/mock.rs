@@ -0,0 +1,99 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+use super::{Capabilities, Provider};
+use crate::config::ProviderConfig;
+use crate::error::LlmError;
+use crate::llm::{ChatResponse, EventHandler, StreamEvent};
+use crate::message::ChatMessage;
+use crate::tools::ToolDefinition;
+
+// One scripted turn: either a reply, or an error response the way an HTTP API would send it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MockStep {
+    Error { error: MockError },
+    Reply(ChatResponse),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MockError {
+    pub status: u16,
+    #[serde(default)]
+    pub body: String,
+}
+
+// Answers requests from a script instead of a real API, one step per request
+#[derive(Debug)]
+pub struct MockProvider {
+    script: Vec<MockStep>,
+    requests: Mutex<Vec<Vec<ChatMessage>>>,
+}
+
+impl MockProvider {
+    pub fn create(config: &ProviderConfig, _client: Client) -> Result<Box<dyn Provider>> {
+        Ok(Box::new(Self::new(config.script.clone())))
+    }
+
+    pub fn new(script: Vec<MockStep>) -> Self {
+        Self {
+            script,
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+
+    // The messages of every request received so far
+    #[cfg(test)]
+    pub fn requests(&self) -> Vec<Vec<ChatMessage>> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    fn next_step(&self, messages: &[ChatMessage]) -> Result<ChatResponse> {
+        let mut requests = self.requests.lock().unwrap();
+        requests.push(messages.to_vec());
+
+        match self.script.get(requests.len() - 1) {
+            Some(MockStep::Reply(response)) => Ok(response.clone()),
+            Some(MockStep::Error { error }) => {
+                let status = StatusCode::from_u16(error.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                Err(LlmError::from_body(Some(status), error.body.clone()).into())
+            }
+            None => Err(LlmError::invalid_response(
+                format!("the mock script has no reply for request {}", requests.len()),
+                "",
+            ).into()),
+        }
+    }
+}
+
+#[async_trait]
+impl Provider for MockProvider {
+    async fn send(&self, messages: &[ChatMessage], _tools: &[ToolDefinition]) -> Result<ChatResponse> {
+        self.next_step(messages)
+    }
+
+    async fn stream(&self, messages: &[ChatMessage], _tools: &[ToolDefinition], on_event: &mut EventHandler<'_>) -> Result<ChatResponse> {
+        let response = self.next_step(messages)?;
+        for word in response.thinking().split_inclusive(' ') {
+            on_event(StreamEvent::Thinking(word));
+        }
+        for word in response.text().split_inclusive(' ') {
+            on_event(StreamEvent::Text(word));
+        }
+        Ok(response)
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>> {
+        Ok(vec!["mock".to_string()])
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            tools: true,
+            streaming: true,
+        }
+    }
+}
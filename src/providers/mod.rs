@@ -0,0 +1,215 @@
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Certificate, Client, Proxy};
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+use std::time::Duration;
+
+use crate::config::{ApiType, ProviderConfig};
+use crate::error::LlmError;
+use crate::llm::{ChatResponse, EventHandler};
+use crate::message::ChatMessage;
+use crate::tools::ToolDefinition;
+
+mod anthropic;
+mod cassette;
+mod gemini;
+mod mock;
+mod openai;
+
+pub use anthropic::AnthropicProvider;
+pub use cassette::{send_http, CassetteProvider};
+pub use gemini::GeminiProvider;
+pub use mock::{MockProvider, MockStep};
+pub use openai::OpenAiProvider;
+
+// One implementation per wire protocol. LlmProvider picks the implementation registered
+// under the provider's protocol name and forwards every call to it.
+#[async_trait]
+pub trait Provider: Send + Sync + std::fmt::Debug {
+    async fn send(&self, messages: &[ChatMessage], tools: &[ToolDefinition]) -> Result<ChatResponse>;
+
+    async fn stream(&self, messages: &[ChatMessage], tools: &[ToolDefinition], on_event: &mut EventHandler<'_>) -> Result<ChatResponse>;
+
+    async fn list_models(&self) -> Result<Vec<String>>;
+
+    fn capabilities(&self) -> Capabilities;
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Capabilities {
+    pub tools: bool,
+    pub streaming: bool,
+}
+
+// Without these a stalled connection or response would hang the session
+const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 600;
+
+pub type ProviderFactory = fn(&ProviderConfig, Client) -> Result<Box<dyn Provider>>;
+
+#[derive(Default)]
+pub struct ProviderRegistry {
+    factories: HashMap<String, ProviderFactory>,
+}
+
+impl ProviderRegistry {
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::default();
+        registry.register("openai", OpenAiProvider::create);
+        registry.register("anthropic", AnthropicProvider::create);
+        registry.register("gemini", GeminiProvider::create);
+        registry.register("mock", MockProvider::create);
+        registry
+    }
+
+    // Makes a protocol available to providers configured with `"api_type": "Custom"`
+    // and `"protocol": "<name>"`
+    pub fn register(&mut self, protocol: &str, factory: ProviderFactory) {
+        self.factories.insert(protocol.to_string(), factory);
+    }
+
+    pub fn create(&self, config: &ProviderConfig, client: Client) -> Result<Box<dyn Provider>> {
+        let protocol = protocol_name(config);
+        match self.factories.get(protocol) {
+            Some(factory) => factory(config, client),
+            None => Err(anyhow!("No provider implementation registered for protocol '{}'", protocol)),
+        }
+    }
+}
+
+pub fn registry() -> &'static RwLock<ProviderRegistry> {
+    static REGISTRY: OnceLock<RwLock<ProviderRegistry>> = OnceLock::new();
+    REGISTRY.get_or_init(|| RwLock::new(ProviderRegistry::with_builtins()))
+}
+
+// The HTTP client for a provider, with its headers, proxy, certificates and timeouts applied
+pub fn http_client(config: &ProviderConfig) -> Result<Client> {
+    let mut headers = HeaderMap::new();
+    for (name, value) in &config.headers {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| anyhow!("Invalid header name '{}'", name))?;
+        let value = HeaderValue::from_str(value)
+            .map_err(|_| anyhow!("Invalid value for header '{}'", name))?;
+        headers.insert(name, value);
+    }
+
+    let mut builder = Client::builder()
+        .default_headers(headers)
+        .connect_timeout(Duration::from_secs(config.connect_timeout.unwrap_or(DEFAULT_CONNECT_TIMEOUT_SECS)))
+        .timeout(Duration::from_secs(config.request_timeout.unwrap_or(DEFAULT_REQUEST_TIMEOUT_SECS)));
+
+    if let Some(proxy) = &config.proxy {
+        let proxy = Proxy::all(proxy).map_err(|e| anyhow!("Invalid proxy '{}': {}", proxy, e))?;
+        builder = builder.proxy(proxy);
+    }
+
+    if let Some(path) = &config.ca_cert_path {
+        let pem = std::fs::read(path)
+            .map_err(|e| anyhow!("Failed to read ca_cert_path {}: {}", path.display(), e))?;
+        let certificates = Certificate::from_pem_bundle(&pem)
+            .map_err(|e| anyhow!("Invalid certificate in {}: {}", path.display(), e))?;
+        if certificates.is_empty() {
+            return Err(anyhow!("No certificates found in {}", path.display()));
+        }
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+
+    builder.build().map_err(|e| anyhow!("Failed to set up the HTTP client: {}", e))
+}
+
+pub fn protocol_name(config: &ProviderConfig) -> &str {
+    match config.api_type {
+        ApiType::OpenAI => "openai",
+        ApiType::Anthropic => "anthropic",
+        ApiType::Gemini => "gemini",
+        ApiType::Mock => "mock",
+        // Custom providers without an explicit protocol keep speaking the OpenAI format
+        ApiType::Custom => config.protocol.as_deref().unwrap_or("openai"),
+    }
+}
+
+pub fn check_range(name: &str, value: Option<f64>, min: f64, max: f64) -> Result<()> {
+    match value {
+        Some(value) if !(min..=max).contains(&value) => {
+            Err(anyhow!("{} must be between {} and {}, got {}", name, min, max, value))
+        }
+        _ => Ok(()),
+    }
+}
+
+// Reads a JSON body, keeping the raw text in the error when it does not parse
+pub async fn read_json(response: reqwest::Response) -> Result<serde_json::Value, LlmError> {
+    let body = response.text().await?;
+    serde_json::from_str(&body)
+        .map_err(|e| LlmError::invalid_response(format!("Response is not valid JSON: {}", e), body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::ChatMessage;
+    use crate::retry::RetrySettings;
+    use wiremock::matchers::{header, method};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn config(server: &MockServer) -> ProviderConfig {
+        ProviderConfig {
+            base_url: Some(server.uri()),
+            retry: Some(RetrySettings { max_attempts: 1, ..Default::default() }),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_configured_headers() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header("x-gateway-tenant", "platform"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "choices": [{ "message": { "role": "assistant", "content": "Hi" } }]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let config = ProviderConfig {
+            headers: HashMap::from([("X-Gateway-Tenant".to_string(), "platform".to_string())]),
+            ..config(&server)
+        };
+        let provider = OpenAiProvider::create(&config, http_client(&config).unwrap()).unwrap();
+        provider.send(&[ChatMessage::user("Hello")], &[]).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_slow_responses() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(5)))
+            .mount(&server)
+            .await;
+
+        let config = ProviderConfig {
+            request_timeout: Some(1),
+            ..config(&server)
+        };
+        let provider = OpenAiProvider::create(&config, http_client(&config).unwrap()).unwrap();
+        let error = provider.send(&[ChatMessage::user("Hello")], &[]).await.unwrap_err();
+        assert!(matches!(error.downcast_ref::<LlmError>(), Some(LlmError::Network(e)) if e.is_timeout()));
+    }
+
+    #[test]
+    fn rejects_invalid_client_settings() {
+        let invalid = [
+            ProviderConfig { headers: HashMap::from([("bad header".to_string(), "x".to_string())]), ..Default::default() },
+            ProviderConfig { proxy: Some("not a url".to_string()), ..Default::default() },
+            ProviderConfig { ca_cert_path: Some("/nonexistent/ca.pem".into()), ..Default::default() },
+        ];
+        for config in invalid {
+            assert!(http_client(&config).is_err());
+        }
+    }
+}
@@ -0,0 +1,752 @@
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use reqwest::Client;
+use serde_json::json;
+use std::collections::BTreeMap;
+
+use super::{check_range, read_json, Capabilities, Provider};
+use crate::config::{AuthStyle, ProviderConfig};
+use crate::llm::{ChatResponse, EventHandler, StreamEvent, Usage};
+use crate::message::{self, ChatMessage, ContentPart, Role};
+use crate::error::LlmError;
+use crate::retry::{self, RetrySettings};
+use crate::sse::SseParser;
+use crate::tools::{ToolCall, ToolDefinition};
+
+// Used for deployments when the provider does not set api_version
+const AZURE_API_VERSION: &str = "2024-10-21";
+
+// Chat Completions protocol, also spoken by DeepSeek, Kimi, Ollama and Azure OpenAI
+#[derive(Debug)]
+pub struct OpenAiProvider {
+    config: ProviderConfig,
+    client: Client,
+}
+
+impl OpenAiProvider {
+    pub fn create(config: &ProviderConfig, client: Client) -> Result<Box<dyn Provider>> {
+        let params = &config.parameters;
+        check_range("temperature", params.temperature, 0.0, 2.0)?;
+        check_range("top_p", params.top_p, 0.0, 1.0)?;
+        if params.max_tokens == Some(0) {
+            return Err(anyhow!("max_tokens must be greater than 0"));
+        }
+        if params.thinking_budget.is_some() {
+            return Err(anyhow!("thinking_budget is not supported by the Chat Completions API; set reasoning_effort instead"));
+        }
+        // Reasoning models only work with the default sampling
+        if Self::reasons(config) {
+            if params.temperature.is_some() {
+                return Err(anyhow!("temperature cannot be set for reasoning models or together with reasoning_effort"));
+            }
+            if params.top_p.is_some() {
+                return Err(anyhow!("top_p cannot be set for reasoning models or together with reasoning_effort"));
+            }
+        }
+        if params.stop.len() > 4 {
+            return Err(anyhow!("At most 4 stop sequences are supported, got {}", params.stop.len()));
+        }
+        if config.deployment.is_some() && config.base_url.is_none() {
+            return Err(anyhow!("A deployment needs the resource's base_url, e.g. https://my-resource.openai.azure.com"));
+        }
+        if config.api_version.is_some() && config.deployment.is_none() {
+            return Err(anyhow!("api_version is only used together with a deployment"));
+        }
+        
+        Ok(Box::new(Self {
+            config: config.clone(),
+            client,
+        }))
+    }
+    
+    // OpenAI's o-series and gpt-5 models, which think before they answer even without
+    // reasoning_effort. Routers prefix them with the vendor, e.g. "openai/o3-mini".
+    pub fn is_reasoning_model(model: &str) -> bool {
+        let model = model.rsplit('/').next().unwrap_or(model);
+        ["o1", "o3", "o4"].iter().any(|prefix| model.starts_with(prefix))
+            || (model.starts_with("gpt-5") && !model.starts_with("gpt-5-chat"))
+    }
+    
+    fn reasons(config: &ProviderConfig) -> bool {
+        config.parameters.reasoning_effort.is_some()
+            || config.model.as_deref().is_some_and(Self::is_reasoning_model)
+    }
+    
+    fn retry_settings(&self) -> RetrySettings {
+        self.config.retry.clone().unwrap_or_default()
+    }
+    
+    fn base_url(&self) -> &str {
+        self.config.base_url.as_deref().unwrap_or("https://api.openai.com/v1")
+    }
+    
+    // Azure deployments carry the model in the path and the API version in the query
+    fn endpoint(&self, path: &str) -> String {
+        let base_url = self.base_url().trim_end_matches('/');
+        match &self.config.deployment {
+            Some(deployment) => format!(
+                "{}/openai/deployments/{}/{}?api-version={}",
+                base_url,
+                deployment,
+                path,
+                self.config.api_version.as_deref().unwrap_or(AZURE_API_VERSION)
+            ),
+            None => format!("{}/{}", base_url, path),
+        }
+    }
+    
+    fn authorize(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        // No header without an API key (for Ollama compatibility)
+        let Some(api_key) = &self.config.api_key else {
+            return request;
+        };
+        let default_style = if self.config.deployment.is_some() { AuthStyle::ApiKey } else { AuthStyle::Bearer };
+        match self.config.auth.unwrap_or(default_style) {
+            AuthStyle::Bearer => request.header("Authorization", format!("Bearer {}", api_key)),
+            AuthStyle::ApiKey => request.header("api-key", api_key),
+        }
+    }
+    
+    // A single message can expand to several on the wire: OpenAI sends each tool result
+    // as its own "tool" message
+    fn wire_messages(message: &ChatMessage) -> Vec<serde_json::Value> {
+        match message.role {
+            Role::Tool => message.content.iter().filter_map(|part| match part {
+                ContentPart::ToolResult { tool_call_id, result, .. } => Some(json!({
+                    "role": "tool",
+                    "tool_call_id": tool_call_id,
+                    "content": result.to_message_content()
+                })),
+                _ => None,
+            }).collect(),
+            Role::Assistant => {
+                let text = message.text();
+                let tool_calls = message.tool_calls();
+                let mut wire = json!({
+                    "role": "assistant",
+                    "content": if text.is_empty() && !tool_calls.is_empty() { serde_json::Value::Null } else { json!(text) }
+                });
+                
+                // DeepSeek needs the reasoning back to continue a turn that called tools, and
+                // rejects it on other turns
+                let thinking = message::thinking_of(&message.content);
+                if !thinking.is_empty() && !tool_calls.is_empty() {
+                    wire["reasoning_content"] = json!(thinking);
+                }
+                
+                if !tool_calls.is_empty() {
+                    wire["tool_calls"] = json!(tool_calls.iter().map(|call| json!({
+                        "id": call.id,
+                        "type": "function",
+                        "function": {
+                            "name": call.name,
+                            // OpenAI expects the arguments as a JSON-encoded string
+                            "arguments": serde_json::to_string(&call.arguments).unwrap_or_else(|_| "{}".to_string())
+                        }
+                    })).collect::<Vec<_>>());
+                }
+                
+                vec![wire]
+            }
+            Role::System | Role::User => {
+                let role = if message.role == Role::System { "system" } else { "user" };
+                let has_images = message.content.iter().any(|part| matches!(part, ContentPart::Image { .. }));
+                
+                if !has_images {
+                    return vec![json!({ "role": role, "content": message.text() })];
+                }
+                
+                let parts: Vec<_> = message.content.iter().filter_map(|part| match part {
+                    ContentPart::Text { text } => Some(json!({ "type": "text", "text": text })),
+                    ContentPart::Image { media_type, data } => Some(json!({
+                        "type": "image_url",
+                        "image_url": { "url": format!("data:{};base64,{}", media_type, data) }
+                    })),
+                    _ => None,
+                }).collect();
+                
+                vec![json!({ "role": role, "content": parts })]
+            }
+        }
+    }
+    
+    fn parse_tool_calls(message: &serde_json::Value) -> Result<Vec<ToolCall>> {
+        let Some(calls) = message["tool_calls"].as_array() else {
+            return Ok(Vec::new());
+        };
+        
+        calls.iter().map(|call| {
+            let name = call["function"]["name"]
+                .as_str()
+                .ok_or_else(|| LlmError::invalid_response("tool call without a function name", call.to_string()))?;
+            
+            // Some OpenAI-compatible servers send the arguments as an object instead of a string
+            let arguments = match &call["function"]["arguments"] {
+                serde_json::Value::String(raw) if raw.trim().is_empty() => Default::default(),
+                serde_json::Value::String(raw) => serde_json::from_str(raw)
+                    .map_err(|e| LlmError::invalid_response(format!("invalid arguments for tool call '{}': {}", name, e), raw.as_str()))?,
+                serde_json::Value::Object(map) => map.clone().into_iter().collect(),
+                _ => Default::default(),
+            };
+            
+            Ok(ToolCall {
+                id: call["id"].as_str()
+                    .map(|id| id.to_string())
+                    .unwrap_or_else(|| format!("call_{}", uuid::Uuid::new_v4().simple())),
+                name: name.to_string(),
+                arguments,
+                description: None,
+            })
+        }).collect()
+    }
+    
+    fn apply_parameters(&self, request_body: &mut serde_json::Value) {
+        let params = &self.config.parameters;
+        
+        // Reasoning models reject max_tokens in favour of max_completion_tokens
+        if let Some(effort) = params.reasoning_effort {
+            request_body["reasoning_effort"] = json!(effort.as_str());
+        }
+        if let Some(max_tokens) = params.max_tokens {
+            let key = if Self::reasons(&self.config) { "max_completion_tokens" } else { "max_tokens" };
+            request_body[key] = json!(max_tokens);
+        }
+        if let Some(temperature) = params.temperature {
+            request_body["temperature"] = json!(temperature);
+        }
+        if let Some(top_p) = params.top_p {
+            request_body["top_p"] = json!(top_p);
+        }
+        if !params.stop.is_empty() {
+            request_body["stop"] = json!(params.stop);
+        }
+        if let Some(seed) = params.seed {
+            request_body["seed"] = json!(seed);
+        }
+    }
+    
+    async fn post(&self, messages: &[ChatMessage], tools: &[ToolDefinition], stream: bool) -> Result<reqwest::Response> {
+        let model = self.config.model.as_deref().unwrap_or("gpt-4");
+        
+        let mut request_body = json!({
+            "model": model,
+            "messages": messages.iter().flat_map(Self::wire_messages).collect::<Vec<_>>()
+        });
+        self.apply_parameters(&mut request_body);
+        
+        if !tools.is_empty() {
+            request_body["tools"] = json!(tools.iter().map(|tool| json!({
+                "type": "function",
+                "function": {
+                    "name": tool.name,
+                    "description": tool.description,
+                    "parameters": tool.parameters
+                }
+            })).collect::<Vec<_>>());
+        }
+        
+        if stream {
+            request_body["stream"] = json!(true);
+            request_body["stream_options"] = json!({ "include_usage": true });
+        }
+        
+        let request_builder = self.client
+            .post(self.endpoint("chat/completions"))
+            .header("Content-Type", "application/json");
+        let request_builder = self.authorize(request_builder).json(&request_body);
+        
+        Ok(retry::send(request_builder, &self.retry_settings()).await?)
+    }
+    
+    // Ollama's own API lives next to the OpenAI-compatible one under /v1
+    async fn list_ollama_models(&self) -> Result<Vec<String>> {
+        let root = self.base_url().trim_end_matches('/').trim_end_matches("/v1");
+        let request_builder = self.client.get(format!("{}/api/tags", root));
+        let response = retry::send(request_builder, &self.retry_settings()).await?;
+        let body = read_json(response).await?;
+        
+        let mut models: Vec<String> = body["models"].as_array()
+            .ok_or_else(|| LlmError::invalid_response("no models array in model list", body.to_string()))?
+            .iter()
+            .filter_map(|model| model["name"].as_str().map(|name| name.to_string()))
+            .collect();
+        models.sort();
+        
+        Ok(models)
+    }
+    
+    // DeepSeek and vLLM send reasoning_content, OpenRouter and Ollama send reasoning. OpenAI's
+    // own models only share a summary, as parts of reasoning.summary, or as reasoning.summary
+    // entries of OpenRouter's reasoning_details.
+    fn reasoning(message: &serde_json::Value) -> Option<String> {
+        if let Some(text) = message["reasoning_content"].as_str().or_else(|| message["reasoning"].as_str()) {
+            return Some(text.to_string()).filter(|text| !text.is_empty());
+        }
+        let parts = message["reasoning"]["summary"].as_array()
+            .or_else(|| message["reasoning_details"].as_array())?;
+        let summary: Vec<&str> = parts.iter()
+            .filter_map(|part| part["text"].as_str().or_else(|| part["summary"].as_str()))
+            .collect();
+        Some(summary.join("\n\n")).filter(|summary| !summary.is_empty())
+    }
+    
+    fn parse_usage(u: &serde_json::Value) -> Option<Usage> {
+        Some(Usage {
+            prompt_tokens: u["prompt_tokens"].as_u64()? as u32,
+            completion_tokens: u["completion_tokens"].as_u64()? as u32,
+            total_tokens: u["total_tokens"].as_u64()? as u32,
+            cached_tokens: u["prompt_tokens_details"]["cached_tokens"].as_u64().unwrap_or_default() as u32,
+            cache_write_tokens: 0,
+        })
+    }
+}
+
+#[async_trait]
+impl Provider for OpenAiProvider {
+    async fn send(&self, messages: &[ChatMessage], tools: &[ToolDefinition]) -> Result<ChatResponse> {
+        let response = self.post(messages, tools, false).await?;
+        let response_body = read_json(response).await?;
+        
+        let message = &response_body["choices"][0]["message"];
+        if !message.is_object() {
+            return Err(LlmError::invalid_response("no message in choices", response_body.to_string()).into());
+        }
+        
+        let mut content = Vec::new();
+        if let Some(thinking) = Self::reasoning(message) {
+            content.push(ContentPart::Thinking { text: thinking, signature: None, redacted: false });
+        }
+        // Content is null when the model only requested tool calls
+        if let Some(text) = message["content"].as_str().filter(|text| !text.is_empty()) {
+            content.push(ContentPart::text(text));
+        }
+        content.extend(Self::parse_tool_calls(message)?.into_iter().map(ContentPart::ToolCall));
+        
+        let usage = Self::parse_usage(&response_body["usage"]);
+        
+        Ok(ChatResponse { content, usage })
+    }
+    
+    async fn stream(&self, messages: &[ChatMessage], tools: &[ToolDefinition], on_event: &mut EventHandler<'_>) -> Result<ChatResponse> {
+        let response = self.post(messages, tools, true).await?;
+        
+        let mut text = String::new();
+        let mut thinking = String::new();
+        // Tool calls arrive as fragments keyed by their index in the final list
+        let mut tool_calls: BTreeMap<u64, serde_json::Value> = BTreeMap::new();
+        let mut usage = None;
+        
+        let mut parser = SseParser::new();
+        let mut body = response.bytes_stream();
+        'stream: while let Some(chunk) = body.next().await {
+            for event in parser.feed(&chunk.map_err(LlmError::from)?) {
+                if event.data == "[DONE]" {
+                    break 'stream;
+                }
+                
+                let chunk: serde_json::Value = serde_json::from_str(&event.data)
+                    .map_err(|e| LlmError::invalid_response(format!("invalid stream chunk: {}", e), event.data.as_str()))?;
+                
+                // Errors after the stream started arrive as a chunk instead of an HTTP status
+                if chunk.get("error").is_some() {
+                    return Err(LlmError::from_body(None, event.data).into());
+                }
+                
+                // The usage chunk sent with include_usage has no choices
+                if let Some(chunk_usage) = Self::parse_usage(&chunk["usage"]) {
+                    usage = Some(chunk_usage);
+                }
+                
+                let delta = &chunk["choices"][0]["delta"];
+                if let Some(fragment) = Self::reasoning(delta) {
+                    thinking.push_str(&fragment);
+                    on_event(StreamEvent::Thinking(&fragment));
+                }
+                if let Some(fragment) = delta["content"].as_str().filter(|f| !f.is_empty()) {
+                    text.push_str(fragment);
+                    on_event(StreamEvent::Text(fragment));
+                }
+                
+                for fragment in delta["tool_calls"].as_array().into_iter().flatten() {
+                    let index = fragment["index"].as_u64().unwrap_or(tool_calls.len() as u64);
+                    let call = tool_calls.entry(index)
+                        .or_insert_with(|| json!({ "function": { "name": "", "arguments": "" } }));
+                    
+                    if let Some(id) = fragment["id"].as_str() {
+                        call["id"] = json!(id);
+                    }
+                    for field in ["name", "arguments"] {
+                        if let Some(part) = fragment["function"][field].as_str() {
+                            let joined = format!("{}{}", call["function"][field].as_str().unwrap_or_default(), part);
+                            call["function"][field] = json!(joined);
+                        }
+                    }
+                }
+            }
+        }
+        
+        let mut content = Vec::new();
+        if !thinking.is_empty() {
+            content.push(ContentPart::Thinking { text: thinking, signature: None, redacted: false });
+        }
+        if !text.is_empty() {
+            content.push(ContentPart::Text { text });
+        }
+        let assembled = json!({ "tool_calls": tool_calls.into_values().collect::<Vec<_>>() });
+        content.extend(Self::parse_tool_calls(&assembled)?.into_iter().map(ContentPart::ToolCall));
+        
+        Ok(ChatResponse { content, usage })
+    }
+    
+    async fn list_models(&self) -> Result<Vec<String>> {
+        // A deployment serves exactly one model; listing the others needs the Azure management API
+        if let Some(deployment) = &self.config.deployment {
+            return Ok(vec![deployment.clone()]);
+        }
+        
+        let request_builder = self.authorize(self.client.get(self.endpoint("models")));
+        let response = match retry::send(request_builder, &self.retry_settings()).await {
+            Ok(response) => response,
+            // Ollama versions without the OpenAI-compatible /models list models natively
+            Err(LlmError::BadEndpoint(_)) => return self.list_ollama_models().await,
+            Err(e) => return Err(e.into()),
+        };
+        let body = read_json(response).await?;
+        
+        let mut models: Vec<String> = body["data"].as_array()
+            .ok_or_else(|| LlmError::invalid_response("no data array in model list", body.to_string()))?
+            .iter()
+            .filter_map(|model| model["id"].as_str().map(|id| id.to_string()))
+            .collect();
+        models.sort();
+        
+        Ok(models)
+    }
+    
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            tools: true,
+            streaming: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ReasoningEffort, SamplingParams};
+    use wiremock::matchers::{body_partial_json, header, header_exists, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+    
+    fn reply() -> ResponseTemplate {
+        ResponseTemplate::new(200).set_body_json(json!({
+            "choices": [{ "message": { "role": "assistant", "content": "Hi" } }],
+            "usage": { "prompt_tokens": 5, "completion_tokens": 1, "total_tokens": 6 }
+        }))
+    }
+    
+    #[tokio::test]
+    async fn keeps_reasoning_for_tool_call_turns() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "choices": [{ "message": {
+                    "role": "assistant",
+                    "content": null,
+                    "reasoning_content": "I should check the date",
+                    "tool_calls": [{ "id": "call_1", "type": "function", "function": { "name": "date", "arguments": "{}" } }]
+                } }]
+            })))
+            .mount(&server)
+            .await;
+        
+        let config = ProviderConfig { base_url: Some(server.uri()), ..Default::default() };
+        let provider = OpenAiProvider::create(&config, Client::new()).unwrap();
+        let response = provider.send(&[ChatMessage::user("What day is it?")], &[]).await.unwrap();
+        assert_eq!(response.thinking(), "I should check the date");
+        
+        let wire = OpenAiProvider::wire_messages(&ChatMessage::assistant(response.content));
+        assert_eq!(wire[0]["reasoning_content"], "I should check the date");
+        // DeepSeek rejects reasoning on turns that did not call tools
+        let answer = ChatMessage::assistant(vec![
+            ContentPart::Thinking { text: "Easy".to_string(), signature: None, redacted: false },
+            ContentPart::text("Monday"),
+        ]);
+        assert!(OpenAiProvider::wire_messages(&answer)[0].get("reasoning_content").is_none());
+    }
+    
+    #[tokio::test]
+    async fn reads_openai_reasoning_summaries() {
+        let server = MockServer::start().await;
+        // OpenRouter streams the summary in reasoning_details, next to encrypted reasoning
+        let events = [
+            json!({ "choices": [{ "delta": { "reasoning_details": [{ "type": "reasoning.summary", "summary": "Checking " }] } }] }),
+            json!({ "choices": [{ "delta": { "reasoning_details": [{ "type": "reasoning.summary", "summary": "the date" }] } }] }),
+            json!({ "choices": [{ "delta": { "reasoning_details": [{ "type": "reasoning.encrypted", "data": "gAAAA" }] } }] }),
+            json!({ "choices": [{ "delta": { "content": "Monday" } }] }),
+        ];
+        let body: String = events.iter().map(|event| format!("data: {}\n\n", event)).collect();
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .and(body_partial_json(json!({ "stream": true })))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(body, "text/event-stream"))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "choices": [{ "message": {
+                    "role": "assistant",
+                    "content": "Monday",
+                    "reasoning": { "summary": [
+                        { "type": "summary_text", "text": "**Checking the date**" },
+                        { "type": "summary_text", "text": "Today is Monday." }
+                    ] }
+                } }]
+            })))
+            .mount(&server)
+            .await;
+        
+        let config = ProviderConfig { base_url: Some(server.uri()), ..Default::default() };
+        let provider = OpenAiProvider::create(&config, Client::new()).unwrap();
+        let messages = [ChatMessage::user("What day is it?")];
+        let response = provider.send(&messages, &[]).await.unwrap();
+        assert_eq!(response.thinking(), "**Checking the date**\n\nToday is Monday.");
+        assert_eq!(response.text(), "Monday");
+        
+        let mut streamed = String::new();
+        let mut on_event = |event: StreamEvent| {
+            if let StreamEvent::Thinking(text) = event {
+                streamed.push_str(text);
+            }
+        };
+        let response = provider.stream(&messages, &[], &mut on_event).await.unwrap();
+        assert_eq!(streamed, "Checking the date");
+        assert_eq!(response.thinking(), "Checking the date");
+        assert_eq!(response.text(), "Monday");
+    }
+    
+    #[tokio::test]
+    async fn reassembles_streamed_tool_calls() {
+        let server = MockServer::start().await;
+        let call = |index: u64, id: Option<&str>, name: Option<&str>, arguments: &str| {
+            let mut fragment = json!({ "index": index, "function": { "arguments": arguments } });
+            if let Some(id) = id {
+                fragment["id"] = json!(id);
+                fragment["type"] = json!("function");
+            }
+            if let Some(name) = name {
+                fragment["function"]["name"] = json!(name);
+            }
+            json!({ "choices": [{ "index": 0, "delta": { "tool_calls": [fragment] } }] })
+        };
+        let events = [
+            json!({ "choices": [{ "index": 0, "delta": { "role": "assistant", "content": "Let me " } }] }),
+            json!({ "choices": [{ "index": 0, "delta": { "content": "look." } }] }),
+            call(0, Some("call_a"), Some("read_file"), ""),
+            call(1, Some("call_b"), Some("grep"), "{\"pat"),
+            call(0, None, None, "{\"path\":"),
+            call(1, None, None, "tern\":\"fn main\"}"),
+            call(0, None, None, "\"src/main.rs\"}"),
+            json!({ "choices": [{ "index": 0, "delta": {}, "finish_reason": "tool_calls" }] }),
+            // Sent last because of include_usage
+            json!({ "choices": [], "usage": { "prompt_tokens": 12, "completion_tokens": 30, "total_tokens": 42 } }),
+        ];
+        let body: String = events.iter()
+            .map(|event| format!("data: {}\n\n", event))
+            .chain(std::iter::once("data: [DONE]\n\n".to_string()))
+            .collect();
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .and(body_partial_json(json!({ "stream": true, "stream_options": { "include_usage": true } })))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(body, "text/event-stream"))
+            .mount(&server)
+            .await;
+        
+        let config = ProviderConfig { base_url: Some(server.uri()), ..Default::default() };
+        let provider = OpenAiProvider::create(&config, Client::new()).unwrap();
+        let mut streamed = String::new();
+        let mut on_event = |event: StreamEvent| {
+            if let StreamEvent::Text(text) = event {
+                streamed.push_str(text);
+            }
+        };
+        let response = provider.stream(&[ChatMessage::user("Where is main?")], &[], &mut on_event).await.unwrap();
+        
+        assert_eq!(streamed, "Let me look.");
+        assert_eq!(response.text(), "Let me look.");
+        let calls = response.tool_calls();
+        assert_eq!(calls.len(), 2);
+        assert_eq!((calls[0].id.as_str(), calls[0].name.as_str()), ("call_a", "read_file"));
+        assert_eq!(calls[0].arguments["path"], "src/main.rs");
+        assert_eq!((calls[1].id.as_str(), calls[1].name.as_str()), ("call_b", "grep"));
+        assert_eq!(calls[1].arguments["pattern"], "fn main");
+        
+        let usage = response.usage.unwrap();
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens, usage.total_tokens), (12, 30, 42));
+    }
+    
+    #[tokio::test]
+    async fn sends_max_completion_tokens_to_reasoning_models() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(reply())
+            .mount(&server)
+            .await;
+        
+        // Recognised by the model id alone, without reasoning_effort
+        let config = ProviderConfig {
+            base_url: Some(server.uri()),
+            model: Some("o3-mini".to_string()),
+            parameters: SamplingParams { max_tokens: Some(4000), ..Default::default() },
+            ..Default::default()
+        };
+        let provider = OpenAiProvider::create(&config, Client::new()).unwrap();
+        provider.send(&[ChatMessage::user("Hi")], &[]).await.unwrap();
+        
+        let requests = server.received_requests().await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        let body = body.as_object().unwrap();
+        assert_eq!(body["max_completion_tokens"], 4000);
+        for rejected in ["temperature", "top_p", "max_tokens", "reasoning_effort"] {
+            assert!(!body.contains_key(rejected), "{} was sent", rejected);
+        }
+    }
+    
+    #[test]
+    fn rejects_sampling_parameters_for_reasoning_models() {
+        let config = |model: &str, parameters| ProviderConfig {
+            model: Some(model.to_string()),
+            parameters,
+            ..Default::default()
+        };
+        let effort = SamplingParams { reasoning_effort: Some(ReasoningEffort::High), ..Default::default() };
+        
+        assert!(OpenAiProvider::create(&config("gpt-4o", SamplingParams { temperature: Some(0.5), ..effort.clone() }), Client::new()).is_err());
+        assert!(OpenAiProvider::create(&config("o1-mini", SamplingParams { top_p: Some(0.9), ..Default::default() }), Client::new()).is_err());
+        assert!(OpenAiProvider::create(&config("gpt-5-chat-latest", SamplingParams { temperature: Some(0.5), ..Default::default() }), Client::new()).is_ok());
+        assert!(OpenAiProvider::create(&config("gpt-5", effort), Client::new()).is_ok());
+    }
+    
+    #[tokio::test]
+    async fn sends_azure_deployment_requests() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/openai/deployments/gpt4o-prod/chat/completions"))
+            .and(query_param("api-version", "2024-06-01"))
+            .and(header("api-key", "azure-key"))
+            .respond_with(reply())
+            .expect(1)
+            .mount(&server)
+            .await;
+        
+        let config = ProviderConfig {
+            api_key: Some("azure-key".to_string()),
+            base_url: Some(format!("{}/", server.uri())),
+            deployment: Some("gpt4o-prod".to_string()),
+            api_version: Some("2024-06-01".to_string()),
+            ..Default::default()
+        };
+        let provider = OpenAiProvider::create(&config, Client::new()).unwrap();
+        
+        let response = provider.send(&[ChatMessage::user("Hello")], &[]).await.unwrap();
+        assert_eq!(response.text(), "Hi");
+        assert_eq!(provider.list_models().await.unwrap(), ["gpt4o-prod"]);
+        
+        let request = &server.received_requests().await.unwrap()[0];
+        assert!(!request.headers.contains_key("authorization"));
+    }
+    
+    #[tokio::test]
+    async fn uses_the_configured_auth_style() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .and(header("api-key", "gateway-key"))
+            .respond_with(reply())
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/bearer/chat/completions"))
+            .and(header("authorization", "Bearer plain-key"))
+            .respond_with(reply())
+            .expect(1)
+            .mount(&server)
+            .await;
+        
+        let api_key_config = ProviderConfig {
+            api_key: Some("gateway-key".to_string()),
+            base_url: Some(format!("{}/v1", server.uri())),
+            auth: Some(AuthStyle::ApiKey),
+            ..Default::default()
+        };
+        let bearer_config = ProviderConfig {
+            api_key: Some("plain-key".to_string()),
+            base_url: Some(format!("{}/bearer", server.uri())),
+            ..Default::default()
+        };
+        for config in [api_key_config, bearer_config] {
+            let provider = OpenAiProvider::create(&config, Client::new()).unwrap();
+            provider.send(&[ChatMessage::user("Hello")], &[]).await.unwrap();
+        }
+    }
+    
+    #[tokio::test]
+    async fn sends_no_auth_header_without_a_key() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header_exists("authorization"))
+            .respond_with(ResponseTemplate::new(401))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(reply())
+            .mount(&server)
+            .await;
+        
+        let config = ProviderConfig {
+            base_url: Some(server.uri()),
+            ..Default::default()
+        };
+        let provider = OpenAiProvider::create(&config, Client::new()).unwrap();
+        assert!(provider.send(&[ChatMessage::user("Hello")], &[]).await.is_ok());
+    }
+    
+    #[tokio::test]
+    async fn lists_ollama_models_from_its_native_api() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v1/models"))
+            .respond_with(ResponseTemplate::new(404).set_body_string("404 page not found"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/tags"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "models": [{ "name": "qwen2.5-coder:7b" }, { "name": "llama3.2:latest" }]
+            })))
+            .mount(&server)
+            .await;
+        
+        let config = ProviderConfig {
+            base_url: Some(format!("{}/v1", server.uri())),
+            retry: Some(RetrySettings { max_attempts: 1, ..Default::default() }),
+            ..Default::default()
+        };
+        let provider = OpenAiProvider::create(&config, Client::new()).unwrap();
+        assert_eq!(provider.list_models().await.unwrap(), ["llama3.2:latest", "qwen2.5-coder:7b"]);
+    }
+    
+    #[test]
+    fn deployments_need_a_base_url() {
+        let config = ProviderConfig {
+            deployment: Some("gpt4o-prod".to_string()),
+            ..Default::default()
+        };
+        assert!(OpenAiProvider::create(&config, Client::new()).is_err());
+    }
+}
@@ -0,0 +1,253 @@
+use colored::*;
+use rand::Rng;
+use reqwest::header::HeaderMap;
+use reqwest::{RequestBuilder, Response};
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+use std::time::Duration;
+
+use crate::error::LlmError;
+use crate::providers;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetrySettings {
+    // Total number of attempts, including the first one
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    #[serde(default = "default_initial_delay_ms")]
+    pub initial_delay_ms: u64,
+    // Upper bound for computed backoff. A server asking for a longer wait is not retried.
+    #[serde(default = "default_max_delay_ms")]
+    pub max_delay_ms: u64,
+}
+
+fn default_max_attempts() -> u32 {
+    4
+}
+
+fn default_initial_delay_ms() -> u64 {
+    1000
+}
+
+fn default_max_delay_ms() -> u64 {
+    60_000
+}
+
+impl Default for RetrySettings {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_max_attempts(),
+            initial_delay_ms: default_initial_delay_ms(),
+            max_delay_ms: default_max_delay_ms(),
+        }
+    }
+}
+
+impl RetrySettings {
+    // Exponential backoff with jitter: a random delay in the upper half of the window
+    fn backoff(&self, attempt: u32) -> Duration {
+        let window = self.initial_delay_ms
+            .saturating_mul(1u64 << (attempt - 1).min(20))
+            .min(self.max_delay_ms);
+        let delay = rand::thread_rng().gen_range(window / 2..=window);
+        Duration::from_millis(delay)
+    }
+}
+
+// Sends the request, retrying transient failures. Non-2xx responses are turned into an
+// LlmError, so callers only ever see successful responses.
+pub async fn send(request: RequestBuilder, settings: &RetrySettings) -> Result<Response, LlmError> {
+    let max_attempts = settings.max_attempts.max(1);
+    let max_delay = Duration::from_millis(settings.max_delay_ms);
+    let mut attempt = 1;
+
+    loop {
+        let Some(this_attempt) = request.try_clone() else {
+            // Streaming bodies cannot be replayed
+            return check(providers::send_http(request).await?).await;
+        };
+
+        let (error, server_delay) = match providers::send_http(this_attempt).await {
+            Ok(response) if response.status().is_success() => return Ok(response),
+            Ok(response) => {
+                let delay = server_delay(response.headers());
+                (LlmError::from_response(response).await, delay)
+            }
+            Err(e) => (e, None),
+        };
+
+        if !error.is_retryable() || attempt >= max_attempts {
+            return Err(error);
+        }
+
+        let delay = match server_delay {
+            // Don't wait on a server that asks for longer than we are willing to back off
+            Some(delay) if delay > max_delay => return Err(error),
+            Some(delay) => delay,
+            None => settings.backoff(attempt),
+        };
+        let reason = match error.status() {
+            Some(status) => format!("HTTP {}", status.as_u16()),
+            None => "Connection error".to_string(),
+        };
+
+        countdown(&reason, delay, attempt + 1, max_attempts).await;
+        attempt += 1;
+    }
+}
+
+async fn check(response: Response) -> Result<Response, LlmError> {
+    if response.status().is_success() {
+        Ok(response)
+    } else {
+        Err(LlmError::from_response(response).await)
+    }
+}
+
+// How long the server asked us to wait, from Retry-After (seconds or HTTP date),
+// retry-after-ms, or Anthropic's rate limit reset timestamps
+pub fn server_delay(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(str::trim);
+
+    if let Some(ms) = header("retry-after-ms").and_then(|v| v.parse::<f64>().ok()) {
+        return Some(Duration::from_millis(ms.max(0.0) as u64));
+    }
+
+    if let Some(value) = header("retry-after") {
+        if let Ok(seconds) = value.parse::<f64>() {
+            return Some(Duration::from_millis((seconds.max(0.0) * 1000.0) as u64));
+        }
+        if let Ok(date) = chrono::DateTime::parse_from_rfc2822(value) {
+            return Some(until(date.with_timezone(&chrono::Utc)));
+        }
+    }
+
+    // Wait for the latest reset among the limits that are exhausted
+    ["requests", "tokens", "input-tokens", "output-tokens"].iter()
+        .filter(|limit| header(&format!("anthropic-ratelimit-{}-remaining", limit)) == Some("0"))
+        .filter_map(|limit| header(&format!("anthropic-ratelimit-{}-reset", limit)))
+        .filter_map(|reset| chrono::DateTime::parse_from_rfc3339(reset).ok())
+        .map(|reset| until(reset.with_timezone(&chrono::Utc)))
+        .max()
+}
+
+fn until(time: chrono::DateTime<chrono::Utc>) -> Duration {
+    (time - chrono::Utc::now()).to_std().unwrap_or_default()
+}
+
+async fn countdown(reason: &str, delay: Duration, next_attempt: u32, max_attempts: u32) {
+    let mut remaining = delay;
+
+    while !remaining.is_zero() {
+        print!("\r\x1B[2K{}", format!(
+            "⏳ {} - retrying in {}s (attempt {}/{})",
+            reason,
+            remaining.as_secs_f64().ceil() as u64,
+            next_attempt,
+            max_attempts
+        ).bright_yellow());
+        let _ = io::stdout().flush();
+
+        let step = remaining.min(Duration::from_secs(1));
+        tokio::time::sleep(step).await;
+        remaining -= step;
+    }
+
+    print!("\r\x1B[2K");
+    let _ = io::stdout().flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::Client;
+    use wiremock::matchers::method;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn headers(pairs: &[(&'static str, String)]) -> HeaderMap {
+        pairs.iter().map(|(name, value)| (name.parse().unwrap(), value.parse().unwrap())).collect()
+    }
+
+    fn settings(max_attempts: u32) -> RetrySettings {
+        RetrySettings { max_attempts, initial_delay_ms: 1, max_delay_ms: 60_000 }
+    }
+
+    #[test]
+    fn reads_the_delay_the_server_asks_for() {
+        assert_eq!(server_delay(&headers(&[])), None);
+        assert_eq!(server_delay(&headers(&[("retry-after", "2".to_string())])), Some(Duration::from_secs(2)));
+        assert_eq!(server_delay(&headers(&[("retry-after", "1.5".to_string())])), Some(Duration::from_millis(1500)));
+        // retry-after-ms is more precise, so it wins
+        assert_eq!(
+            server_delay(&headers(&[("retry-after", "2".to_string()), ("retry-after-ms", "250".to_string())])),
+            Some(Duration::from_millis(250))
+        );
+
+        let in_30s = chrono::Utc::now() + chrono::Duration::seconds(30);
+        let delay = server_delay(&headers(&[("retry-after", in_30s.to_rfc2822())])).unwrap();
+        assert!(delay > Duration::from_secs(28) && delay <= Duration::from_secs(30));
+        let past = chrono::Utc::now() - chrono::Duration::seconds(30);
+        assert_eq!(server_delay(&headers(&[("retry-after", past.to_rfc2822())])), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn waits_for_exhausted_anthropic_limits_only() {
+        let in_20s = (chrono::Utc::now() + chrono::Duration::seconds(20)).to_rfc3339();
+        let in_50s = (chrono::Utc::now() + chrono::Duration::seconds(50)).to_rfc3339();
+        let delay = server_delay(&headers(&[
+            ("anthropic-ratelimit-requests-remaining", "0".to_string()),
+            ("anthropic-ratelimit-requests-reset", in_20s.clone()),
+            ("anthropic-ratelimit-tokens-remaining", "1200".to_string()),
+            ("anthropic-ratelimit-tokens-reset", in_50s),
+        ])).unwrap();
+        assert!(delay > Duration::from_secs(18) && delay <= Duration::from_secs(20));
+
+        let not_exhausted = headers(&[
+            ("anthropic-ratelimit-requests-remaining", "3".to_string()),
+            ("anthropic-ratelimit-requests-reset", in_20s),
+        ]);
+        assert_eq!(server_delay(&not_exhausted), None);
+    }
+
+    #[tokio::test]
+    async fn retries_until_the_server_recovers() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("ok"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let response = send(Client::new().get(server.uri()), &settings(2)).await.unwrap();
+        assert_eq!(response.text().await.unwrap(), "ok");
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_long_waits_and_exhausted_quotas() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(429).insert_header("retry-after", "120"))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let error = send(Client::new().get(server.uri()), &settings(4)).await.unwrap_err();
+        assert!(matches!(error, LlmError::RateLimit(_)));
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(429).set_body_json(serde_json::json!({
+                "error": { "message": "You exceeded your current quota", "code": "insufficient_quota" }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let error = send(Client::new().get(server.uri()), &settings(4)).await.unwrap_err();
+        assert!(!error.is_retryable());
+    }
+}
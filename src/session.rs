@@ -1,17 +1,30 @@
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use colored::*;
-use crossterm::{
-    event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
-    terminal::{disable_raw_mode, enable_raw_mode},
-};
-use dialoguer::{Input, Confirm};
+use dialoguer::{Input, Select, theme::ColorfulTheme};
 use serde::{Deserialize, Serialize};
 use std::io::{self, Write};
 use std::path::PathBuf;
 use tokio::fs;
 use uuid::Uuid;
 
-use crate::{llm::{LlmProvider, ChatMessage, ChatResponse}, tools::ToolExecutor};
+use crate::{
+    error::LlmError,
+    config::GlobalSettings,
+    context::{self, Compaction},
+    interrupt,
+    llm::{ChatResponse, LlmProvider, StreamEvent, Usage},
+    message::{self, ChatMessage, ContentPart, MessageMetadata, Role},
+    models,
+    pricing::UsageReport,
+    tools::{ToolCall, ToolDefinition, ToolExecutor, ToolResult},
+};
+
+// Text streamed before a request was interrupted, and who was writing it
+#[derive(Default)]
+struct PartialReply {
+    text: String,
+    metadata: MessageMetadata,
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Session {
@@ -20,51 +33,69 @@ pub struct Session {
     pub messages: Vec<ChatMessage>,
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub updated_at: chrono::DateTime<chrono::Utc>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub compactions: Vec<Compaction>,
     
     #[serde(skip)]
     provider: Option<LlmProvider>,
     
+    // Tried in order when the provider fails with a retryable error
+    #[serde(skip)]
+    fallbacks: Vec<LlmProvider>,
+    
     #[serde(skip)]
     tool_executor: Option<ToolExecutor>,
     
     #[serde(skip)]
     yolo_mode: bool,
+    
+    #[serde(skip)]
+    max_tool_steps: u32,
+    
+    #[serde(skip)]
+    stream: bool,
+    
+    #[serde(skip)]
+    auto_compact: bool,
+    
+    // Print the model's thinking, or collapse it into a one-line note
+    #[serde(skip)]
+    show_thinking: bool,
+    
+    // Running token and cost totals, rebuilt from the messages when the session is loaded
+    #[serde(skip)]
+    usage: UsageReport,
+    
+    // Whether changes are written to the session file
+    #[serde(skip)]
+    persist: bool,
 }
 
 impl Session {
     pub async fn new_or_load(
         session_name: Option<String>,
         provider: LlmProvider,
+        settings: &GlobalSettings,
         yolo_mode: bool,
     ) -> Result<Self> {
         let session_name = session_name.unwrap_or_else(|| "default".to_string());
         let session_path = Self::session_path(&session_name);
         
-        let mut session = if session_path.exists() {
+        let session = if session_path.exists() {
             let content = fs::read_to_string(&session_path).await?;
             let mut session: Session = serde_json::from_str(&content)?;
             session.provider = Some(provider);
             session.tool_executor = Some(ToolExecutor::new(yolo_mode));
             session.yolo_mode = yolo_mode;
+            session.max_tool_steps = settings.max_tool_steps;
+            session.stream = settings.stream;
+            session.auto_compact = settings.auto_compact;
+            session.show_thinking = settings.show_thinking;
+            session.usage = session.usage_report();
+            session.persist = true;
             session
         } else {
-            let mut session = Self {
-                id: Uuid::new_v4().to_string(),
-                name: session_name,
-                messages: vec![
-                    ChatMessage::system(
-                        "You are Crush, a helpful AI coding assistant. You can help with coding tasks, \
-                        explain code, suggest improvements, and run tools when needed. Always be concise \
-                        and helpful. When you need to run tools or execute commands, ask for permission \
-                        unless the user has enabled yolo mode."
-                    )
-                ],
-                created_at: chrono::Utc::now(),
-                updated_at: chrono::Utc::now(),
-                provider: Some(provider),
-                tool_executor: Some(ToolExecutor::new(yolo_mode)),
-                yolo_mode,
-            };
+            let session = Self::new(session_name, provider, settings, yolo_mode);
             session.save().await?;
             session
         };
@@ -72,74 +103,480 @@ impl Session {
         Ok(session)
     }
     
+    fn new(name: String, provider: LlmProvider, settings: &GlobalSettings, yolo_mode: bool) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            name,
+            messages: vec![
+                ChatMessage::system(
+                    "You are Crush, a helpful AI coding assistant. You can help with coding tasks, \
+                    explain code, suggest improvements, and run tools when needed. Always be concise \
+                    and helpful. Use the available tools to inspect files and run commands instead of \
+                    guessing; the user is asked to approve anything that is not read-only unless \
+                    yolo mode is enabled."
+                )
+            ],
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+            compactions: Vec::new(),
+            provider: Some(provider),
+            fallbacks: Vec::new(),
+            tool_executor: Some(ToolExecutor::new(yolo_mode)),
+            yolo_mode,
+            max_tool_steps: settings.max_tool_steps,
+            stream: settings.stream,
+            auto_compact: settings.auto_compact,
+            show_thinking: settings.show_thinking,
+            usage: UsageReport::default(),
+            persist: true,
+        }
+    }
+    
+    // A yolo-mode session that is never written to disk
+    #[cfg(test)]
+    fn ephemeral(provider: LlmProvider, settings: &GlobalSettings) -> Self {
+        let mut session = Self::new("test".to_string(), provider, settings, true);
+        session.persist = false;
+        session
+    }
+    
+    pub fn with_fallbacks(mut self, fallbacks: Vec<LlmProvider>) -> Self {
+        self.fallbacks = fallbacks;
+        self
+    }
+    
     pub async fn send_message(&mut self, message: &str) -> Result<()> {
         let user_message = ChatMessage::user(message);
         self.messages.push(user_message);
         
         println!("\n{} {}", "You:".bright_cyan().bold(), message);
         
-        match self.provider.as_ref().unwrap().send_message(&self.messages).await {
-            Ok(response) => {
-                let assistant_message = ChatMessage::assistant(&response.content);
-                self.messages.push(assistant_message);
-                
-                println!("\n{} {}", "Crush:".bright_magenta().bold(), response.content);
-                
-                if let Some(usage) = response.usage {
-                    println!("{}", format!(
-                        "({} tokens used)", 
-                        usage.total_tokens
-                    ).dimmed());
+        let tools = self.tool_definitions();
+        
+        for step in 0..self.max_tool_steps {
+            let mut partial = PartialReply::default();
+            let Some(result) = interrupt::cancellable(self.next_reply(&tools, &mut partial)).await else {
+                return self.keep_interrupted_reply(step, partial).await;
+            };
+            
+            let (response, metadata) = match result {
+                Ok(answer) => answer,
+                Err(e) => {
+                    if step == 0 {
+                        // Remove the user message since we got an error
+                        self.messages.pop();
+                    } else {
+                        // Keep the tool results gathered so far so the turn can be continued
+                        self.updated_at = chrono::Utc::now();
+                        self.save().await?;
+                    }
+                    
+                    Self::show_error(&e);
+                    return Err(e);
                 }
-                
+            };
+            
+            if let Some(usage) = &metadata.usage {
+                let cost = metadata.cost
+                    .map(|cost| format!(", ${:.4}", cost))
+                    .unwrap_or_default();
+                println!("{}", format!(
+                    "({} tokens used{})", 
+                    usage.total_tokens,
+                    cost
+                ).dimmed());
+            }
+            
+            let tool_calls = response.tool_calls();
+            let mut reply = ChatMessage::assistant(response.content);
+            reply.metadata = metadata;
+            self.usage.add_message(&reply);
+            self.messages.push(reply);
+            
+            if tool_calls.is_empty() {
                 self.updated_at = chrono::Utc::now();
                 self.save().await?;
+                return Ok(());
             }
-            Err(e) => {
-                // Remove the user message since we got an error
-                self.messages.pop();
-                
-                println!("\n{} {}", "❌ Error:".red().bold(), e.to_string().bright_red());
-                
-                // Provide helpful suggestions based on error type
-                let error_str = e.to_string();
-                if error_str.contains("Cloudflare") {
-                    println!("{}", "\n💡 Suggestions:".bright_yellow().bold());
-                    println!("   • Check if your base_url is correct");
-                    println!("   • Some providers may be blocked by Cloudflare");
-                    println!("   • Try using a different provider or VPN");
-                } else if error_str.contains("Authentication") || error_str.contains("unauthorized") {
-                    println!("{}", "\n💡 Suggestions:".bright_yellow().bold());
-                    println!("   • Verify your API key is correct and active");
-                    println!("   • Check if your API key has sufficient permissions");
-                } else if error_str.contains("Rate limit") {
-                    println!("{}", "\n💡 Suggestions:".bright_yellow().bold());
-                    println!("   • Wait a moment and try again");
-                    println!("   • Consider upgrading your API plan");
-                } else if error_str.contains("endpoint not found") {
-                    println!("{}", "\n💡 Suggestions:".bright_yellow().bold());
-                    println!("   • Check your base_url configuration");
-                    println!("   • Verify the API endpoint is correct");
-                }
-                
-                return Err(e);
+            
+            // Every tool call needs a result, so calls after an interrupted one are skipped
+            let mut results = Vec::new();
+            let mut interrupted = false;
+            for tool_call in tool_calls {
+                let result = if interrupted {
+                    ToolResult::failure("Skipped because the user interrupted an earlier tool call")
+                } else if let Some(result) = interrupt::cancellable(self.run_tool(&tool_call)).await.flatten() {
+                    result
+                } else {
+                    println!("{}", "⏹  Interrupted".bright_yellow());
+                    interrupted = true;
+                    ToolResult::failure("Interrupted by the user")
+                };
+                results.push((tool_call, result));
+            }
+            let mut tool_results = ChatMessage::tool_results(results);
+            tool_results.metadata.interrupted = interrupted;
+            self.messages.push(tool_results);
+            
+            self.updated_at = chrono::Utc::now();
+            self.save().await?;
+            
+            if interrupted {
+                return Ok(());
             }
         }
         
+        println!("\n{}", format!(
+            "⚠️  Stopped after {} tool steps. Send another message to let Crush continue.",
+            self.max_tool_steps
+        ).bright_yellow());
+        
         Ok(())
     }
     
+    // Compacts the conversation if needed and asks for the next reply. Text streamed so far
+    // is mirrored into `partial` so it survives an interruption.
+    async fn next_reply(&mut self, tools: &[ToolDefinition], partial: &mut PartialReply) -> Result<(ChatResponse, MessageMetadata)> {
+        if self.auto_compact && self.needs_compaction(tools) {
+            self.compact(tools).await;
+        }
+        
+        let result = self.request_completion(tools, partial).await;
+        
+        // The token estimate is rough; if the provider still says the prompt is too long,
+        // compact and try once more
+        let too_long = result.as_ref().err()
+            .and_then(|e| e.downcast_ref::<LlmError>())
+            .is_some_and(|e| matches!(e, LlmError::ContextLength(_)));
+        if too_long && self.auto_compact && self.compact(tools).await {
+            return self.request_completion(tools, partial).await;
+        }
+        
+        result
+    }
+    
+    // Ctrl+C ended the request. Text that was already streamed stays in the transcript,
+    // marked as interrupted.
+    async fn keep_interrupted_reply(&mut self, step: u32, partial: PartialReply) -> Result<()> {
+        println!("{}", "⏹  Interrupted".bright_yellow());
+        
+        if !partial.text.is_empty() {
+            let mut reply = ChatMessage::assistant(vec![ContentPart::text(partial.text)]);
+            reply.metadata = partial.metadata;
+            reply.metadata.interrupted = true;
+            self.messages.push(reply);
+        } else if step == 0 {
+            // Nothing came back for this message, so drop it as if it was never sent
+            self.messages.pop();
+            return Ok(());
+        }
+        
+        self.updated_at = chrono::Utc::now();
+        self.save().await
+    }
+    
+    // Asks the provider for the next reply, walking the fallback providers when it is rate
+    // limited or down. Returns the reply along with who produced it.
+    async fn request_completion(&self, tools: &[ToolDefinition], partial: &mut PartialReply) -> Result<(ChatResponse, MessageMetadata)> {
+        let providers: Vec<&LlmProvider> = self.provider.iter().chain(&self.fallbacks).collect();
+        
+        for (i, provider) in providers.iter().enumerate() {
+            *partial = PartialReply {
+                text: String::new(),
+                metadata: Self::answered_by(provider, None),
+            };
+            let error = match self.complete_with(provider, tools, &mut partial.text).await {
+                Ok(response) => {
+                    let metadata = Self::answered_by(provider, response.usage.as_ref());
+                    return Ok((response, metadata));
+                }
+                Err(e) => e,
+            };
+            
+            let retryable = error.downcast_ref::<LlmError>().is_some_and(LlmError::is_retryable);
+            match providers.get(i + 1) {
+                Some(next) if retryable => {
+                    println!("\n{}", format!(
+                        "⚠️  {} failed: {}. Falling back to {}...",
+                        provider.name,
+                        error.to_string().lines().next().unwrap_or_default().trim_end_matches('.'),
+                        next.name
+                    ).bright_yellow());
+                }
+                _ => return Err(error),
+            }
+        }
+        
+        Err(anyhow!("No LLM provider configured for this session"))
+    }
+    
+    fn answered_by(provider: &LlmProvider, usage: Option<&Usage>) -> MessageMetadata {
+        MessageMetadata {
+            provider: Some(provider.name.clone()),
+            model: provider.config.model.clone(),
+            usage: usage.cloned(),
+            cost: usage.zip(provider.price()).map(|(usage, price)| price.cost(usage)),
+            cache_savings: usage
+                .filter(|usage| usage.cached_tokens > 0 || usage.cache_write_tokens > 0)
+                .zip(provider.price())
+                .map(|(usage, price)| price.cache_savings(usage)),
+            ..Default::default()
+        }
+    }
+    
+    // Space the prompt may take up without crowding out the reply
+    fn prompt_budget(&self) -> Option<usize> {
+        self.provider.as_ref()
+            .map(|p| context::prompt_budget(p.context_window(), p.config.parameters.max_tokens))
+    }
+    
+    fn needs_compaction(&self, tools: &[ToolDefinition]) -> bool {
+        self.prompt_budget()
+            .is_some_and(|budget| context::needs_compaction(context::estimate_tokens(&self.messages, tools), budget))
+    }
+    
+    // Replaces older messages with a summary written by the current provider, keeping the
+    // system prompt and recent messages. Returns whether anything was compacted.
+    async fn compact(&mut self, tools: &[ToolDefinition]) -> bool {
+        let (Some(provider), Some(budget)) = (self.provider.clone(), self.prompt_budget()) else {
+            return false;
+        };
+        let Some(range) = context::compaction_range(&self.messages, budget) else {
+            return false;
+        };
+        
+        let tokens_before = context::estimate_tokens(&self.messages, tools);
+        println!("\n{}", format!(
+            "🗜️  Compacting {} earlier messages (~{} tokens in context)...",
+            range.len(),
+            tokens_before
+        ).dimmed());
+        
+        let request = context::summary_request(&self.messages[range.clone()]);
+        let response = match provider.send_message(&request, &[]).await {
+            Ok(response) if !response.text().trim().is_empty() => response,
+            Ok(_) => {
+                println!("{}", "⚠️  Compaction failed: the provider returned an empty summary".bright_yellow());
+                return false;
+            }
+            Err(e) => {
+                println!("{}", format!(
+                    "⚠️  Compaction failed: {}",
+                    e.to_string().lines().next().unwrap_or_default()
+                ).bright_yellow());
+                return false;
+            }
+        };
+        
+        let mut summary = context::summary_message(&response.text());
+        summary.metadata = Self::answered_by(&provider, response.usage.as_ref());
+        self.usage.add_message(&summary);
+        let replaced = self.messages.splice(range, [summary]).collect();
+        let tokens_after = context::estimate_tokens(&self.messages, tools);
+        
+        self.compactions.push(Compaction {
+            timestamp: chrono::Utc::now(),
+            provider: provider.name.clone(),
+            model: provider.config.model.clone(),
+            tokens_before,
+            tokens_after,
+            messages: replaced,
+        });
+        
+        println!("{}", format!("   Context is now ~{} tokens", tokens_after).dimmed());
+        true
+    }
+    
+    async fn complete_with(&self, provider: &LlmProvider, tools: &[ToolDefinition], streamed: &mut String) -> Result<ChatResponse> {
+        let capabilities = provider.capabilities();
+        let tools = if capabilities.tools { tools } else { &[] };
+        
+        if !(self.stream && capabilities.streaming) {
+            let response = provider.send_message(&self.messages, tools).await?;
+            let thinking = response.thinking();
+            if !thinking.is_empty() {
+                if self.show_thinking {
+                    println!("\n{} {}", "💭".dimmed(), thinking.dimmed());
+                } else {
+                    Self::show_collapsed_thinking(&thinking);
+                }
+            }
+            let text = response.text();
+            if !text.is_empty() {
+                println!("\n{} {}", "Crush:".bright_magenta().bold(), text);
+            }
+            return Ok(response);
+        }
+        
+        let show_thinking = self.show_thinking;
+        let mut started = false;
+        let mut thinking = String::new();
+        // Thinking comes before the answer; it is printed as it arrives, or noted once it is over
+        let end_thinking = |thinking: &mut String| {
+            if !thinking.is_empty() {
+                if show_thinking {
+                    println!();
+                } else {
+                    Self::show_collapsed_thinking(thinking);
+                }
+                thinking.clear();
+            }
+        };
+        let mut on_event = |event: StreamEvent| {
+            match event {
+                StreamEvent::Thinking(text) => {
+                    if show_thinking {
+                        if thinking.is_empty() {
+                            print!("\n{} ", "💭".dimmed());
+                        }
+                        print!("{}", text.dimmed());
+                    }
+                    thinking.push_str(text);
+                }
+                StreamEvent::Text(text) => {
+                    if !started {
+                        end_thinking(&mut thinking);
+                        print!("\n{} ", "Crush:".bright_magenta().bold());
+                        started = true;
+                    }
+                    print!("{}", text);
+                    streamed.push_str(text);
+                }
+            }
+            let _ = io::stdout().flush();
+        };
+        
+        let result = provider.stream_message(&self.messages, tools, &mut on_event).await;
+        if started {
+            println!();
+        } else {
+            end_thinking(&mut thinking);
+        }
+        
+        result
+    }
+    
+    fn show_collapsed_thinking(thinking: &str) {
+        println!("\n{}", format!(
+            "💭 Thought for {} words (:thinking to show)",
+            thinking.split_whitespace().count()
+        ).dimmed());
+    }
+    
+    // `:thinking` switches between printing the model's thinking and collapsing it. Switching
+    // it on shows what the last reply thought.
+    fn toggle_thinking(&mut self) {
+        self.show_thinking = !self.show_thinking;
+        if !self.show_thinking {
+            println!("{}", "Thinking is now collapsed".dimmed());
+            return;
+        }
+        
+        println!("{}", "Thinking is now shown".dimmed());
+        let last_thinking = self.messages.iter().rev()
+            .take_while(|m| m.role != Role::User)
+            .map(|m| message::thinking_of(&m.content))
+            .filter(|thinking| !thinking.is_empty())
+            .collect::<Vec<_>>();
+        for thinking in last_thinking.iter().rev() {
+            println!("\n{} {}", "💭".dimmed(), thinking.dimmed());
+        }
+    }
+    
+    // Returns None when the user pressed Ctrl+C at the approval prompt
+    async fn run_tool(&self, tool_call: &ToolCall) -> Option<ToolResult> {
+        println!("\n{} {} {}", 
+            "🔧 Tool:".bright_blue().bold(),
+            tool_call.name.bright_white(),
+            serde_json::to_string(&tool_call.arguments).unwrap_or_default().dimmed()
+        );
+        
+        let result = match self.tool_executor.as_ref() {
+            Some(executor) => match executor.execute_tool(tool_call).await {
+                Ok(result) => result,
+                Err(e) if e.is::<interrupt::Cancelled>() => return None,
+                Err(e) => ToolResult::failure(e.to_string()),
+            },
+            None => ToolResult::failure("Tools are not available in this session"),
+        };
+        
+        if !result.success {
+            let error = result.error.as_deref().unwrap_or(&result.output);
+            println!("{} {}", "   failed:".red(), error.lines().next().unwrap_or("").bright_red());
+        }
+        
+        Some(result)
+    }
+    
+    fn show_error(e: &anyhow::Error) {
+        println!("\n{} {}", "❌ Error:".red().bold(), e.to_string().bright_red());
+        
+        // Provide helpful suggestions based on error type
+        let suggestions: &[&str] = match e.downcast_ref::<LlmError>() {
+            Some(LlmError::BadEndpoint(error)) if error.is_cloudflare() => &[
+                "Check if your base_url is correct",
+                "Some providers may be blocked by Cloudflare",
+                "Try using a different provider or VPN",
+            ],
+            Some(LlmError::BadEndpoint(_)) => &[
+                "Check your base_url configuration",
+                "Verify the API endpoint is correct",
+            ],
+            Some(LlmError::Auth(_)) => &[
+                "Verify your API key is correct and active",
+                "Check if your API key has sufficient permissions",
+            ],
+            Some(LlmError::RateLimit(_)) => &[
+                "Wait a moment and try again",
+                "Consider upgrading your API plan",
+            ],
+            Some(LlmError::ContextLength(_)) => &[
+                "Start a new session with --session <name>",
+                "Ask for smaller tool outputs, e.g. read parts of large files",
+            ],
+            Some(LlmError::Network(e)) if e.is_timeout() => &[
+                "The provider did not answer in time; it may be overloaded",
+                "Raise request_timeout or connect_timeout in the provider config",
+            ],
+            Some(LlmError::Network(_)) => &[
+                "Check your internet connection",
+                "Check the proxy and ca_cert_path settings if you are behind a gateway",
+                "Verify the host in your base_url is reachable",
+            ],
+            _ => &[],
+        };
+        
+        if !suggestions.is_empty() {
+            println!("{}", "\n💡 Suggestions:".bright_yellow().bold());
+            for suggestion in suggestions {
+                println!("   • {}", suggestion);
+            }
+        }
+    }
+    
+    fn tool_definitions(&self) -> Vec<ToolDefinition> {
+        self.tool_executor.as_ref()
+            .map(|executor| executor.definitions())
+            .unwrap_or_default()
+    }
+    
     pub async fn start_interactive_loop(&mut self) -> Result<()> {
         println!("\n{}", "Type your message and press Enter. Type 'exit' to quit.".dimmed());
-        println!("{}", "Use Ctrl+C to interrupt at any time.".dimmed());
+        println!("{}", "Press Ctrl+C to interrupt a reply or tool, twice to exit.".dimmed());
         
         loop {
             print!("\n{} ", "➤".bright_green().bold());
             io::stdout().flush()?;
             
-            let input: String = Input::new()
-                .allow_empty(false)
-                .interact_text()?;
+            let input: String = match Input::new().allow_empty(false).interact_text() {
+                Ok(input) => input,
+                // Ctrl+C at the prompt; a second one exits
+                Err(dialoguer::Error::IO(e)) if e.kind() == io::ErrorKind::Interrupted => {
+                    interrupt::press();
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
             
             match input.trim() {
                 "exit" | "quit" | ":q" => {
@@ -158,6 +595,27 @@ impl Session {
                     self.show_status();
                     continue;
                 }
+                command if command == ":model" || command.starts_with(":model ") => {
+                    let model = command.trim_start_matches(":model").trim();
+                    self.switch_model(model).await?;
+                    continue;
+                }
+                ":thinking" => {
+                    self.toggle_thinking();
+                    continue;
+                }
+                ":compact" => {
+                    let tools = self.tool_definitions();
+                    match interrupt::cancellable(self.compact(&tools)).await {
+                        Some(true) => {
+                            self.updated_at = chrono::Utc::now();
+                            self.save().await?;
+                        }
+                        Some(false) => println!("{}", "Nothing to compact.".dimmed()),
+                        None => println!("{}", "⏹  Interrupted".bright_yellow()),
+                    }
+                    continue;
+                }
                 _ => {
                     match self.send_message(&input).await {
                         Ok(_) => {},
@@ -173,12 +631,73 @@ impl Session {
         Ok(())
     }
     
+    // `:model` picks from the provider's model list, `:model <id>` switches directly. The new
+    // model is used from the next request on; the session keeps its history.
+    async fn switch_model(&mut self, requested: &str) -> Result<()> {
+        let Some(provider) = self.provider.clone() else {
+            println!("{}", "No provider configured for this session".bright_yellow());
+            return Ok(());
+        };
+        
+        let model = if requested.is_empty() {
+            let models = match interrupt::cancellable(models::list(&provider, false)).await {
+                Some(Ok(models)) if !models.is_empty() => models,
+                Some(Ok(_)) => {
+                    println!("{}", format!("{} did not list any models. Use :model <id> to switch by name.", provider.name).bright_yellow());
+                    return Ok(());
+                }
+                Some(Err(e)) => {
+                    println!("{}", format!(
+                        "⚠️  Could not list models: {}\n   Use :model <id> to switch by name.",
+                        e.to_string().lines().next().unwrap_or_default()
+                    ).bright_yellow());
+                    return Ok(());
+                }
+                None => {
+                    println!("{}", "⏹  Interrupted".bright_yellow());
+                    return Ok(());
+                }
+            };
+            
+            let current = models.iter()
+                .position(|model| Some(model) == provider.config.model.as_ref())
+                .unwrap_or(0);
+            let selection = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt(format!("Select a {} model", provider.name))
+                .items(&models)
+                .default(current)
+                .interact_opt();
+            match selection {
+                Ok(Some(index)) => models[index].clone(),
+                // Esc, q or Ctrl+C keep the current model
+                Ok(None) => return Ok(()),
+                Err(dialoguer::Error::IO(e)) if e.kind() == io::ErrorKind::Interrupted => return Ok(()),
+                Err(e) => return Err(e.into()),
+            }
+        } else {
+            requested.to_string()
+        };
+        
+        match provider.with_model(&model).await {
+            Ok(switched) => {
+                self.provider = Some(switched);
+                println!("{}", format!("✅ Now using {} on {}", model, provider.name).green());
+            }
+            Err(e) => println!("{}", format!("❌ Could not switch to {}: {}", model, e).bright_red()),
+        }
+        
+        Ok(())
+    }
+    
     fn show_help(&self) {
         println!("\n{}", "Crush Commands:".bright_cyan().bold());
         println!("  {}  Exit the session", "exit, quit, :q".bright_white());
         println!("  {}  Clear the screen", "clear, :clear".bright_white());
         println!("  {}  Show this help", "help, :help".bright_white());
         println!("  {}  Show session status", "status, :status".bright_white());
+        println!("  {}  Summarize older messages to free up context", ":compact".bright_white());
+        println!("  {}  Switch the model, picking from the provider's list", ":model [id]".bright_white());
+        println!("  {}  Show or collapse the model's thinking", ":thinking".bright_white());
     }
     
     fn show_status(&self) {
@@ -186,12 +705,31 @@ impl Session {
         println!("  Name: {}", self.name.bright_white());
         println!("  ID: {}", self.id.dimmed());
         println!("  Messages: {}", self.messages.len().to_string().bright_white());
+        if let Some(provider) = &self.provider {
+            println!("  Context: ~{} / {} tokens{}",
+                context::estimate_tokens(&self.messages, &self.tool_definitions()).to_string().bright_white(),
+                provider.context_window(),
+                match self.compactions.len() {
+                    0 => String::new(),
+                    1 => " (compacted once)".to_string(),
+                    n => format!(" (compacted {} times)", n),
+                }
+            );
+        }
         println!("  Provider: {}", 
             self.provider.as_ref()
                 .map(|p| p.name.as_str())
                 .unwrap_or("None")
                 .bright_white()
         );
+        println!("  Model: {}", 
+            self.provider.as_ref()
+                .and_then(|p| p.config.model.as_deref())
+                .unwrap_or("default")
+                .bright_white()
+        );
+        println!("  Usage:");
+        self.usage.print("    ");
         println!("  YOLO Mode: {}", 
             if self.yolo_mode { "ON".red().bold() } else { "OFF".green() }
         );
@@ -200,6 +738,10 @@ impl Session {
     }
     
     async fn save(&self) -> Result<()> {
+        if !self.persist {
+            return Ok(());
+        }
+        
         let session_path = Self::session_path(&self.name);
         
         if let Some(parent) = session_path.parent() {
@@ -234,6 +776,23 @@ impl Session {
         Ok(sessions)
     }
     
+    // Usage of every request in the session, including compacted messages
+    fn usage_report(&self) -> UsageReport {
+        let mut report = UsageReport::default();
+        let compacted = self.compactions.iter().flat_map(|c| &c.messages);
+        for message in compacted.chain(&self.messages) {
+            report.add_message(message);
+        }
+        report
+    }
+    
+    // Reads a saved session's usage without setting up a provider
+    pub async fn load_usage(name: &str) -> Result<UsageReport> {
+        let content = fs::read_to_string(Self::session_path(name)).await?;
+        let session: Session = serde_json::from_str(&content)?;
+        Ok(session.usage_report())
+    }
+    
     fn session_path(name: &str) -> PathBuf {
         Self::sessions_dir().join(format!("{}.json", name))
     }
@@ -244,4 +803,257 @@ impl Session {
             .join("crush")
             .join("sessions")
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{CassetteMode, CassetteSettings, ProviderConfig};
+    use crate::message::Role;
+    use crate::providers::{CassetteProvider, MockProvider, OpenAiProvider, Provider};
+    use reqwest::Client;
+    use serde_json::json;
+    use std::sync::Arc;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+    
+    fn settings() -> GlobalSettings {
+        GlobalSettings {
+            auto_approve_safe_tools: true,
+            max_tokens: None,
+            temperature: None,
+            max_tool_steps: 25,
+            stream: true,
+            retry: Default::default(),
+            auto_compact: true,
+            show_thinking: true,
+        }
+    }
+    
+    fn mock(name: &str, script: serde_json::Value) -> (Arc<MockProvider>, LlmProvider) {
+        let backend = Arc::new(MockProvider::new(serde_json::from_value(script).unwrap()));
+        let config = ProviderConfig {
+            model: Some("mock".to_string()),
+            ..Default::default()
+        };
+        (backend.clone(), LlmProvider::with_backend(name, config, backend))
+    }
+    
+    fn echo_call(id: &str, message: &str) -> serde_json::Value {
+        json!({"type": "tool_call", "id": id, "name": "echo", "arguments": {"message": message}})
+    }
+    
+    fn text(content: &str) -> serde_json::Value {
+        json!({"content": [{"type": "text", "text": content}], "usage": {"prompt_tokens": 10, "completion_tokens": 2, "total_tokens": 12}})
+    }
+    
+    fn roles(session: &Session) -> Vec<Role> {
+        session.messages.iter().map(|message| message.role).collect()
+    }
+    
+    #[tokio::test]
+    async fn runs_tools_until_the_model_answers() {
+        let (backend, provider) = mock("mock", json!([
+            {"content": [echo_call("c1", "hello")]},
+            text("The tool said hello"),
+        ]));
+        let mut session = Session::ephemeral(provider, &settings());
+        
+        session.send_message("Say hello with the echo tool").await.unwrap();
+        
+        assert_eq!(roles(&session), [Role::System, Role::User, Role::Assistant, Role::Tool, Role::Assistant]);
+        assert_eq!(session.messages.last().unwrap().text(), "The tool said hello");
+        
+        // The second request carries the tool result
+        let requests = backend.requests();
+        assert_eq!(requests.len(), 2);
+        let ContentPart::ToolResult { result, .. } = &requests[1][3].content[0] else {
+            panic!("expected a tool result");
+        };
+        assert!(result.success);
+        assert!(result.output.contains("hello"));
+        assert_eq!(session.usage.total.requests, 1);
+    }
+    
+    #[tokio::test]
+    async fn ctrl_c_at_the_approval_prompt_interrupts_the_turn() {
+        fn ctrl_c() -> dialoguer::Result<bool> {
+            Err(io::Error::from(io::ErrorKind::Interrupted).into())
+        }
+        let shell_call = |id: &str| json!({"type": "tool_call", "id": id, "name": "shell", "arguments": {"command": "echo unused"}});
+        
+        let (backend, provider) = mock("mock", json!([
+            {"content": [shell_call("c1"), shell_call("c2")]},
+            text("unused"),
+        ]));
+        let mut session = Session::ephemeral(provider, &settings());
+        session.tool_executor = Some(ToolExecutor::new(false).with_prompt(ctrl_c));
+        
+        session.send_message("Run two commands").await.unwrap();
+        
+        assert_eq!(backend.requests().len(), 1);
+        let tool_results = session.messages.last().unwrap();
+        assert!(tool_results.metadata.interrupted);
+        let errors: Vec<_> = tool_results.content.iter()
+            .map(|part| match part {
+                ContentPart::ToolResult { result, .. } => result.error.clone().unwrap_or_default(),
+                _ => panic!("expected a tool result"),
+            })
+            .collect();
+        assert_eq!(errors, [
+            "Interrupted by the user",
+            "Skipped because the user interrupted an earlier tool call",
+        ]);
+    }
+    
+    #[tokio::test]
+    async fn stops_after_max_tool_steps() {
+        let (backend, provider) = mock("mock", json!([
+            {"content": [echo_call("c1", "one")]},
+            {"content": [echo_call("c2", "two")]},
+            {"content": [echo_call("c3", "three")]},
+        ]));
+        let mut session = Session::ephemeral(provider, &GlobalSettings { max_tool_steps: 2, ..settings() });
+        
+        session.send_message("Keep echoing").await.unwrap();
+        
+        assert_eq!(backend.requests().len(), 2);
+        assert_eq!(session.messages.last().unwrap().role, Role::Tool);
+    }
+    
+    #[tokio::test]
+    async fn falls_back_when_rate_limited() {
+        let (_, primary) = mock("primary", json!([
+            {"error": {"status": 429, "body": r#"{"error":{"message":"slow down","type":"rate_limit_error"}}"#}},
+        ]));
+        let (backup_backend, backup) = mock("backup", json!([text("Answer from the backup")]));
+        let mut session = Session::ephemeral(primary, &settings()).with_fallbacks(vec![backup]);
+        
+        session.send_message("Hello").await.unwrap();
+        
+        let reply = session.messages.last().unwrap();
+        assert_eq!(reply.text(), "Answer from the backup");
+        assert_eq!(reply.metadata.provider.as_deref(), Some("backup"));
+        assert_eq!(backup_backend.requests().len(), 1);
+    }
+    
+    #[tokio::test]
+    async fn does_not_fall_back_on_auth_errors() {
+        let (_, primary) = mock("primary", json!([
+            {"error": {"status": 401, "body": r#"{"error":{"message":"bad key","code":"invalid_api_key"}}"#}},
+        ]));
+        let (backup_backend, backup) = mock("backup", json!([text("unused")]));
+        let mut session = Session::ephemeral(primary, &settings()).with_fallbacks(vec![backup]);
+        
+        let error = session.send_message("Hello").await.unwrap_err();
+        
+        assert!(matches!(error.downcast_ref::<LlmError>(), Some(LlmError::Auth(_))));
+        assert!(backup_backend.requests().is_empty());
+        // The failed message is not kept
+        assert_eq!(roles(&session), [Role::System]);
+    }
+    
+    #[tokio::test]
+    async fn compacts_and_retries_when_the_prompt_is_too_long() {
+        let (backend, provider) = mock("mock", json!([
+            {"error": {"status": 400, "body": r#"{"error":{"message":"too long","code":"context_length_exceeded"}}"#}},
+            text("The user asked two questions"),
+            text("Final answer"),
+        ]));
+        let mut session = Session::ephemeral(provider, &settings());
+        for i in 0..2 {
+            session.messages.push(ChatMessage::user(format!("Question {}", i)));
+            session.messages.push(ChatMessage::assistant(vec![ContentPart::text(format!("Answer {}", i))]));
+        }
+        
+        session.send_message("One more question").await.unwrap();
+        
+        assert_eq!(session.compactions.len(), 1);
+        assert!(session.messages[1].text().contains("The user asked two questions"));
+        assert_eq!(session.messages.last().unwrap().text(), "Final answer");
+        
+        // The retried request was sent with the summary in place of the old messages
+        let requests = backend.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[2].len() < requests[0].len());
+    }
+    
+    #[tokio::test]
+    async fn replays_a_recorded_cassette() {
+        let settings = CassetteSettings {
+            path: PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/echo_tools.json"),
+            mode: CassetteMode::Replay,
+        };
+        // The OpenAI adapter parses the recorded stream; nothing is sent
+        let config = ProviderConfig::default();
+        let backend: Arc<dyn Provider> = CassetteProvider::create(&settings, || OpenAiProvider::create(&config, Client::new()))
+            .unwrap()
+            .into();
+        let provider = LlmProvider::with_backend("recorded", config.clone(), backend);
+        let mut session = Session::ephemeral(provider, &self::settings());
+        
+        session.send_message("Echo a and b").await.unwrap();
+        
+        assert_eq!(roles(&session), [Role::System, Role::User, Role::Assistant, Role::Tool, Role::Assistant]);
+        assert_eq!(session.messages.last().unwrap().text(), "Héllo wörld done: b");
+        
+        // Anything the recording did not see is an error rather than a network request
+        assert!(session.send_message("Something else").await.is_err());
+    }
+    
+    fn sse(events: &[serde_json::Value]) -> ResponseTemplate {
+        let body: String = events.iter()
+            .map(|event| format!("data: {}\n\n", event))
+            .chain(std::iter::once("data: [DONE]\n\n".to_string()))
+            .collect();
+        ResponseTemplate::new(200).set_body_raw(body, "text/event-stream")
+    }
+    
+    #[tokio::test]
+    async fn records_and_replays_http_traffic() {
+        let dir = tempfile::tempdir().unwrap();
+        let clock = dir.path().join("clock.txt");
+        std::fs::write(&clock, "10:00").unwrap();
+        
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(sse(&[json!({ "choices": [{ "delta": { "tool_calls": [{
+                "index": 0, "id": "call_1", "type": "function",
+                "function": { "name": "read_file", "arguments": json!({ "path": clock }).to_string() }
+            }] } }] })]))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(sse(&[json!({ "choices": [{ "delta": { "content": "It is late" } }] })]))
+            .mount(&server)
+            .await;
+        
+        let config = ProviderConfig { base_url: Some(server.uri()), ..Default::default() };
+        let provider = |mode| {
+            let cassette = CassetteSettings { path: dir.path().join("clock.json"), mode };
+            let backend: Arc<dyn Provider> = CassetteProvider::create(&cassette, || OpenAiProvider::create(&config, Client::new()))
+                .unwrap()
+                .into();
+            LlmProvider::with_backend("recorded", config.clone(), backend)
+        };
+        
+        let mut recording = Session::ephemeral(provider(CassetteMode::Record), &settings());
+        recording.send_message("What time is it?").await.unwrap();
+        assert_eq!(server.received_requests().await.unwrap().len(), 2);
+        
+        // The clock has moved on, so the tool output differs from the recorded one
+        std::fs::write(&clock, "10:05").unwrap();
+        let mut replaying = Session::ephemeral(provider(CassetteMode::Replay), &settings());
+        replaying.send_message("What time is it?").await.unwrap();
+        assert_eq!(server.received_requests().await.unwrap().len(), 2);
+        assert_eq!(roles(&replaying), [Role::System, Role::User, Role::Assistant, Role::Tool, Role::Assistant]);
+        let ContentPart::ToolResult { result, .. } = &replaying.messages[3].content[0] else {
+            panic!("expected a tool result");
+        };
+        assert!(result.output.contains("10:05"));
+        assert_eq!(replaying.messages.last().unwrap().text(), "It is late");
+    }
+}
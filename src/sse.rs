@@ -0,0 +1,114 @@
+// Incremental parser for server-sent event streams. Bytes are fed in as they arrive
+// from the network and complete events are handed back once their blank line is seen.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SseEvent {
+    pub event: Option<String>,
+    pub data: String,
+}
+
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buffer: Vec<u8>,
+    event: Option<String>,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut events = Vec::new();
+        // Lines are only decoded once complete so multi-byte characters split across chunks survive
+        while let Some(newline) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=newline).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+
+            if let Some(event) = self.process_line(line) {
+                events.push(event);
+            }
+        }
+
+        events
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<SseEvent> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+
+        // Comment lines are used as keep-alives
+        if line.starts_with(':') {
+            return None;
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+
+        match field {
+            "event" => self.event = Some(value.to_string()),
+            "data" => self.data.push(value.to_string()),
+            _ => {}
+        }
+
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        if self.data.is_empty() {
+            self.event = None;
+            return None;
+        }
+
+        Some(SseEvent {
+            event: self.event.take(),
+            data: std::mem::take(&mut self.data).join("\n"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One byte at a time, the worst case for where chunk boundaries fall
+    fn parse_bytewise(stream: &str) -> Vec<SseEvent> {
+        let mut parser = SseParser::new();
+        stream.as_bytes().chunks(1).flat_map(|byte| parser.feed(byte)).collect()
+    }
+
+    fn event(event: Option<&str>, data: &str) -> SseEvent {
+        SseEvent { event: event.map(str::to_string), data: data.to_string() }
+    }
+
+    #[test]
+    fn parses_events_fed_byte_by_byte() {
+        let stream = ": keep-alive\r\n\r\n\
+            event: message_start\r\ndata: {\"text\":\"héllo 👋\"}\r\n\r\n\
+            data: first line\ndata:second line\n\n\
+            : ping\n\
+            data: [DONE]\n\n";
+
+        assert_eq!(parse_bytewise(stream), vec![
+            event(Some("message_start"), "{\"text\":\"héllo 👋\"}"),
+            event(None, "first line\nsecond line"),
+            event(None, "[DONE]"),
+        ]);
+    }
+
+    #[test]
+    fn waits_for_the_blank_line() {
+        let mut parser = SseParser::new();
+        assert!(parser.feed(b"event: ping\ndata: {}\n").is_empty());
+        // An event without data is dropped, and its name does not leak into the next one
+        assert_eq!(parser.feed(b"\nevent: unused\n\n"), vec![event(Some("ping"), "{}")]);
+        assert_eq!(parser.feed(b"data: x\n\n"), vec![event(None, "x")]);
+    }
+}
@@ -2,29 +2,63 @@ use anyhow::{Result, anyhow};
 use colored::*;
 use dialoguer::Confirm;
 use serde::{Deserialize, Serialize};
-use std::process::Command;
+use tokio::process::Command;
+use serde_json::json;
 use std::collections::HashMap;
 
+mod edit;
+mod patch;
+mod read;
+mod search;
+
+// Child processes are killed if the tool is cancelled while they run
+fn process(program: &str) -> Command {
+    let mut command = Command::new(program);
+    command.kill_on_drop(true);
+    command
+}
+
+// Asks the user whether to run a tool. It blocks on the terminal, so it runs off the
+// async runtime.
+type Prompt = fn() -> dialoguer::Result<bool>;
+
+fn confirm() -> dialoguer::Result<bool> {
+    Confirm::new()
+        .with_prompt("Do you want to execute this tool?")
+        .default(false)
+        .interact()
+}
+
 #[derive(Debug, Clone)]
 pub struct ToolExecutor {
     yolo_mode: bool,
     safe_tools: Vec<String>,
+    prompt: Prompt,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolCall {
+    #[serde(default)]
+    pub id: String,
     pub name: String,
     pub arguments: HashMap<String, serde_json::Value>,
     pub description: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolResult {
     pub success: bool,
     pub output: String,
     pub error: Option<String>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
+}
+
 impl ToolExecutor {
     pub fn new(yolo_mode: bool) -> Self {
         Self {
@@ -37,10 +71,190 @@ impl ToolExecutor {
                 "git_log".to_string(),
                 "which".to_string(),
                 "echo".to_string(),
+                "grep".to_string(),
+                "glob".to_string(),
             ],
+            prompt: confirm,
         }
     }
 
+    #[cfg(test)]
+    pub fn with_prompt(mut self, prompt: Prompt) -> Self {
+        self.prompt = prompt;
+        self
+    }
+
+    pub fn definitions(&self) -> Vec<ToolDefinition> {
+        vec![
+            ToolDefinition::new(
+                "shell",
+                "Run a shell command in the current directory and return its output",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "command": { "type": "string", "description": "The command line to execute" }
+                    },
+                    "required": ["command"]
+                }),
+            ),
+            ToolDefinition::new(
+                "list_files",
+                "List the contents of a directory",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "path": { "type": "string", "description": "Directory to list (defaults to .)" }
+                    }
+                }),
+            ),
+            ToolDefinition::new(
+                "read_file",
+                "Read a text file. Each line starts with its line number and a tab, which are not part \
+                of the file. Long files are cut off with a note; use offset and limit to read further.",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "path": { "type": "string", "description": "Path of the file to read" },
+                        "offset": { "type": "integer", "description": "First line to read, starting at 1 (defaults to 1)" },
+                        "limit": { "type": "integer", "description": "Number of lines to read (defaults to 2000)" }
+                    },
+                    "required": ["path"]
+                }),
+            ),
+            ToolDefinition::new(
+                "grep",
+                "Search file contents with a regular expression, skipping hidden and .gitignored files. \
+                Returns path:line:text for each match.",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "pattern": { "type": "string", "description": "Regular expression (Rust regex syntax)" },
+                        "path": { "type": "string", "description": "File or directory to search (defaults to .)" },
+                        "include": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "Only search files matching one of these globs, e.g. [\"*.rs\"]"
+                        },
+                        "exclude": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "Skip files matching one of these globs"
+                        },
+                        "context": { "type": "integer", "description": "Lines of context around each match (defaults to 0)" },
+                        "case_sensitive": { "type": "boolean", "description": "Defaults to true" },
+                        "max_results": { "type": "integer", "description": "Maximum number of matching lines (defaults to 100)" }
+                    },
+                    "required": ["pattern"]
+                }),
+            ),
+            ToolDefinition::new(
+                "glob",
+                "Find files whose path matches a glob such as src/**/*.rs, skipping hidden and \
+                .gitignored files. Returns paths relative to the working directory, most recently \
+                modified first.",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "pattern": {
+                            "type": "string",
+                            "description": "Glob matched against paths below `path`; * stays within a directory, ** crosses directories"
+                        },
+                        "path": { "type": "string", "description": "Directory to search (defaults to .)" },
+                        "max_results": { "type": "integer", "description": "Maximum number of paths (defaults to 100)" }
+                    },
+                    "required": ["pattern"]
+                }),
+            ),
+            ToolDefinition::new(
+                "write_file",
+                "Create or overwrite a file with the given content",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "path": { "type": "string", "description": "Path of the file to write" },
+                        "content": { "type": "string", "description": "Full new content of the file" }
+                    },
+                    "required": ["path", "content"]
+                }),
+            ),
+            ToolDefinition::new(
+                "edit_file",
+                "Replace an exact piece of text in a file and return a diff of the change. Prefer this \
+                over write_file for changing existing files.",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "path": { "type": "string", "description": "Path of the file to edit" },
+                        "old_string": {
+                            "type": "string",
+                            "description": "Text to replace, copied exactly from the file including indentation. \
+                                It must match only once unless replace_all is set."
+                        },
+                        "new_string": { "type": "string", "description": "Text to put in its place" },
+                        "replace_all": { "type": "boolean", "description": "Replace every match (defaults to false)" }
+                    },
+                    "required": ["path", "old_string", "new_string"]
+                }),
+            ),
+            ToolDefinition::new(
+                "apply_patch",
+                "Apply a unified diff that can create, change, delete or rename several files. Either \
+                every hunk applies or no file is changed; returns a report per hunk.",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "patch": {
+                            "type": "string",
+                            "description": "Unified diff with ---/+++ file headers (/dev/null to create or delete a file) and @@ hunks"
+                        }
+                    },
+                    "required": ["patch"]
+                }),
+            ),
+            ToolDefinition::new(
+                "get_current_directory",
+                "Return the current working directory",
+                json!({ "type": "object", "properties": {} }),
+            ),
+            ToolDefinition::new(
+                "git_status",
+                "Show the git working tree status in porcelain format",
+                json!({ "type": "object", "properties": {} }),
+            ),
+            ToolDefinition::new(
+                "git_log",
+                "Show recent git commits, one per line",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "limit": { "type": "integer", "description": "Number of commits to show (defaults to 10)" }
+                    }
+                }),
+            ),
+            ToolDefinition::new(
+                "which",
+                "Locate an executable on the PATH",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "command": { "type": "string", "description": "Name of the executable" }
+                    },
+                    "required": ["command"]
+                }),
+            ),
+            ToolDefinition::new(
+                "echo",
+                "Echo a message back",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "message": { "type": "string", "description": "Message to echo" }
+                    }
+                }),
+            ),
+        ]
+    }
+
     pub async fn execute_tool(&self, tool_call: &ToolCall) -> Result<ToolResult> {
         let is_safe = self.is_safe_tool(&tool_call.name);
 
@@ -53,10 +267,15 @@ impl ToolExecutor {
             println!("  Description: {}", description.dimmed());
             println!("  Arguments: {}", serde_json::to_string_pretty(&tool_call.arguments)?);
 
-            let should_execute = Confirm::new()
-                .with_prompt("Do you want to execute this tool?")
-                .default(false)
-                .interact()?;
+            let should_execute = match tokio::task::spawn_blocking(self.prompt).await? {
+                Ok(answer) => answer,
+                // Ctrl+C cancels the turn, as it does while a tool runs; a second one exits
+                Err(dialoguer::Error::IO(e)) if e.kind() == std::io::ErrorKind::Interrupted => {
+                    crate::interrupt::press();
+                    return Err(crate::interrupt::Cancelled.into());
+                }
+                Err(e) => return Err(e.into()),
+            };
 
             if !should_execute {
                 return Ok(ToolResult {
@@ -71,7 +290,11 @@ impl ToolExecutor {
             "shell" | "bash" | "cmd" => self.execute_shell_command(tool_call).await,
             "list_files" | "ls" => self.list_files(tool_call).await,
             "read_file" | "cat" => self.read_file(tool_call).await,
+            "grep" => self.grep(tool_call).await,
+            "glob" => self.glob(tool_call).await,
             "write_file" => self.write_file(tool_call).await,
+            "edit_file" => self.edit_file(tool_call).await,
+            "apply_patch" => self.apply_patch(tool_call).await,
             "get_current_directory" | "pwd" => self.get_current_directory().await,
             "git_status" => self.git_status().await,
             "git_log" => self.git_log(tool_call).await,
@@ -93,13 +316,13 @@ impl ToolExecutor {
         println!("{} {}", "Executing:".bright_blue().bold(), command.bright_white());
 
         let output = if cfg!(target_os = "windows") {
-            Command::new("cmd")
+            process("cmd")
                 .args(["/C", command])
-                .output()?
+                .output().await?
         } else {
-            Command::new("sh")
+            process("sh")
                 .args(["-c", command])
-                .output()?
+                .output().await?
         };
 
         let stdout = String::from_utf8_lossy(&output.stdout).to_string();
@@ -125,14 +348,15 @@ impl ToolExecutor {
             .and_then(|v| v.as_str())
             .unwrap_or(".");
 
+        // dir is built into cmd rather than a program of its own
         let output = if cfg!(target_os = "windows") {
-            Command::new("dir")
-                .arg(path)
-                .output()?
+            process("cmd")
+                .args(["/C", "dir", path])
+                .output().await?
         } else {
-            Command::new("ls")
+            process("ls")
                 .args(["-la", path])
-                .output()?
+                .output().await?
         };
 
         let stdout = String::from_utf8_lossy(&output.stdout).to_string();
@@ -151,17 +375,80 @@ impl ToolExecutor {
             .and_then(|v| v.as_str())
             .ok_or_else(|| anyhow!("Missing 'path' argument"))?;
 
-        match tokio::fs::read_to_string(file_path).await {
-            Ok(content) => Ok(ToolResult {
+        let offset = tool_call.arguments.get("offset")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(1) as usize;
+        let limit = tool_call.arguments.get("limit")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(read::DEFAULT_LIMIT as u64) as usize;
+
+        // Reading a large file blocks, so it runs off the async threads
+        let path = file_path.to_string();
+        match tokio::task::spawn_blocking(move || read::read_file(&path, offset, limit)).await? {
+            Ok(output) => Ok(ToolResult {
                 success: true,
-                output: content,
+                output,
                 error: None,
             }),
-            Err(e) => Ok(ToolResult {
-                success: false,
-                output: String::new(),
-                error: Some(e.to_string()),
+            Err(e) => Ok(ToolResult::failure(e)),
+        }
+    }
+
+    async fn grep(&self, tool_call: &ToolCall) -> Result<ToolResult> {
+        let pattern = tool_call.arguments.get("pattern")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Missing 'pattern' argument"))?;
+
+        let options = search::GrepOptions {
+            pattern: pattern.to_string(),
+            path: tool_call.arguments.get("path")
+                .and_then(|v| v.as_str())
+                .unwrap_or(".")
+                .to_string(),
+            include: string_list(tool_call.arguments.get("include")),
+            exclude: string_list(tool_call.arguments.get("exclude")),
+            context: tool_call.arguments.get("context")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(0) as usize,
+            case_sensitive: tool_call.arguments.get("case_sensitive")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(true),
+            max_results: tool_call.arguments.get("max_results")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(100) as usize,
+        };
+
+        // Walking a large tree blocks, so it runs off the async threads
+        match tokio::task::spawn_blocking(move || search::grep(&options)).await? {
+            Ok(output) => Ok(ToolResult {
+                success: true,
+                output,
+                error: None,
             }),
+            Err(e) => Ok(ToolResult::failure(e)),
+        }
+    }
+
+    async fn glob(&self, tool_call: &ToolCall) -> Result<ToolResult> {
+        let pattern = tool_call.arguments.get("pattern")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Missing 'pattern' argument"))?
+            .to_string();
+        let path = tool_call.arguments.get("path")
+            .and_then(|v| v.as_str())
+            .unwrap_or(".")
+            .to_string();
+        let max_results = tool_call.arguments.get("max_results")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(100) as usize;
+
+        match tokio::task::spawn_blocking(move || search::glob(&pattern, &path, max_results)).await? {
+            Ok(output) => Ok(ToolResult {
+                success: true,
+                output,
+                error: None,
+            }),
+            Err(e) => Ok(ToolResult::failure(e)),
         }
     }
 
@@ -188,6 +475,79 @@ impl ToolExecutor {
         }
     }
 
+    async fn edit_file(&self, tool_call: &ToolCall) -> Result<ToolResult> {
+        let file_path = tool_call.arguments.get("path")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Missing 'path' argument"))?;
+        let old_string = tool_call.arguments.get("old_string")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Missing 'old_string' argument"))?;
+        let new_string = tool_call.arguments.get("new_string")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Missing 'new_string' argument"))?;
+        let replace_all = tool_call.arguments.get("replace_all")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+
+        let bytes = match tokio::fs::read(file_path).await {
+            Ok(bytes) => bytes,
+            Err(e) => return Ok(ToolResult::failure(format!("Cannot read {}: {}", file_path, e))),
+        };
+        // Rewriting other encodings as UTF-8 would change every line of the file
+        let Ok(content) = String::from_utf8(bytes) else {
+            return Ok(ToolResult::failure(format!("{} is not UTF-8 text; edit_file only changes UTF-8 files", file_path)));
+        };
+
+        let edit = match edit::replace(&content, old_string, new_string, replace_all) {
+            Ok(edit) => edit,
+            Err(e) => return Ok(ToolResult::failure(format!("{}: {}", file_path, e))),
+        };
+
+        match tokio::fs::write(file_path, &edit.content).await {
+            Ok(_) => Ok(ToolResult {
+                success: true,
+                output: format!(
+                    "Replaced {} {} in {}\n\n{}",
+                    edit.replacements,
+                    if edit.replacements == 1 { "match" } else { "matches" },
+                    file_path,
+                    edit::unified_diff(file_path, &content, &edit.content)
+                ),
+                error: None,
+            }),
+            Err(e) => Ok(ToolResult::failure(e.to_string())),
+        }
+    }
+
+    async fn apply_patch(&self, tool_call: &ToolCall) -> Result<ToolResult> {
+        let text = tool_call.arguments.get("patch")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Missing 'patch' argument"))?;
+
+        let files = match patch::parse(text) {
+            Ok(files) => files,
+            Err(e) => return Ok(ToolResult::failure(e)),
+        };
+        // Nothing is written unless every hunk of every file applies
+        let plan = match patch::plan(&files, &std::env::current_dir()?).await {
+            Ok(plan) => plan,
+            Err(report) => return Ok(ToolResult {
+                success: false,
+                output: report.join("\n"),
+                error: None,
+            }),
+        };
+
+        match patch::write(&plan).await {
+            Ok(_) => Ok(ToolResult {
+                success: true,
+                output: plan.report.join("\n"),
+                error: None,
+            }),
+            Err(e) => Ok(ToolResult::failure(e)),
+        }
+    }
+
     async fn get_current_directory(&self) -> Result<ToolResult> {
         match std::env::current_dir() {
             Ok(path) => Ok(ToolResult {
@@ -204,9 +564,9 @@ impl ToolExecutor {
     }
 
     async fn git_status(&self) -> Result<ToolResult> {
-        let output = Command::new("git")
+        let output = process("git")
             .args(["status", "--porcelain"])
-            .output()?;
+            .output().await?;
 
         let stdout = String::from_utf8_lossy(&output.stdout).to_string();
 
@@ -224,9 +584,9 @@ impl ToolExecutor {
             .and_then(|v| v.as_u64())
             .unwrap_or(10);
 
-        let output = Command::new("git")
+        let output = process("git")
             .args(["log", "--oneline", &format!("-{}", limit)])
-            .output()?;
+            .output().await?;
 
         let stdout = String::from_utf8_lossy(&output.stdout).to_string();
 
@@ -245,13 +605,13 @@ impl ToolExecutor {
             .ok_or_else(|| anyhow!("Missing 'command' argument"))?;
 
         let output = if cfg!(target_os = "windows") {
-            Command::new("where")
+            process("where")
                 .arg(command)
-                .output()?
+                .output().await?
         } else {
-            Command::new("which")
+            process("which")
                 .arg(command)
-                .output()?
+                .output().await?
         };
 
         let stdout = String::from_utf8_lossy(&output.stdout).to_string();
@@ -277,3 +637,53 @@ impl ToolExecutor {
         })
     }
 }
+
+// A list argument, also accepted as a single string since models sometimes send one
+fn string_list(value: Option<&serde_json::Value>) -> Vec<String> {
+    match value {
+        Some(serde_json::Value::String(item)) => vec![item.clone()],
+        Some(serde_json::Value::Array(items)) => items.iter()
+            .filter_map(|item| item.as_str().map(|item| item.to_string()))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+impl ToolDefinition {
+    pub fn new(name: &str, description: &str, parameters: serde_json::Value) -> Self {
+        Self {
+            name: name.to_string(),
+            description: description.to_string(),
+            parameters,
+        }
+    }
+}
+
+impl ToolResult {
+    pub fn failure(error: impl Into<String>) -> Self {
+        Self {
+            success: false,
+            output: String::new(),
+            error: Some(error.into()),
+        }
+    }
+
+    // Text sent back to the model as the result of the call
+    pub fn to_message_content(&self) -> String {
+        let mut content = if self.success {
+            self.output.clone()
+        } else {
+            format!("Tool failed.\n{}", self.output)
+        };
+
+        if let Some(error) = &self.error {
+            content.push_str(&format!("\nError: {}", error));
+        }
+
+        if content.trim().is_empty() {
+            content = "(no output)".to_string();
+        }
+
+        content
+    }
+}
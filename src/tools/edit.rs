@@ -0,0 +1,95 @@
+use similar::TextDiff;
+
+// The result of replacing text in a file
+#[derive(Debug)]
+pub struct Edit {
+    pub content: String,
+    pub replacements: usize,
+}
+
+// Replaces `old` with `new` in `content`. Models write "\n" even when the file uses "\r\n",
+// so both strings are converted to the file's line endings first. Errors are meant for the
+// model to correct its call.
+pub fn replace(content: &str, old: &str, new: &str, replace_all: bool) -> Result<Edit, String> {
+    if old.is_empty() {
+        return Err("old_string must not be empty; use write_file to create a file".to_string());
+    }
+    if old == new {
+        return Err("old_string and new_string are the same, so there is nothing to change".to_string());
+    }
+
+    let (old, new) = if uses_crlf(content) && !old.contains('\r') {
+        (old.replace('\n', "\r\n"), new.replace('\n', "\r\n"))
+    } else {
+        (old.to_string(), new.to_string())
+    };
+
+    let matches: Vec<usize> = content.match_indices(&old).map(|(index, _)| index).collect();
+    match matches.len() {
+        0 => Err("old_string was not found. It must match the file exactly, including whitespace \
+            and indentation; read the file again to check its current content."
+            .to_string()),
+        1 => Ok(Edit {
+            content: content.replacen(&old, &new, 1),
+            replacements: 1,
+        }),
+        count if replace_all => Ok(Edit {
+            content: content.replace(&old, &new),
+            replacements: count,
+        }),
+        count => {
+            let lines: Vec<String> = matches.iter()
+                .map(|&index| (content[..index].matches('\n').count() + 1).to_string())
+                .collect();
+            Err(format!(
+                "old_string matches {} places (lines {}). Include more surrounding lines to make it \
+                unique, or set replace_all to replace every match.",
+                count,
+                lines.join(", ")
+            ))
+        }
+    }
+}
+
+// Most line breaks are "\r\n"
+pub fn uses_crlf(content: &str) -> bool {
+    let crlf = content.matches("\r\n").count();
+    crlf > 0 && crlf * 2 >= content.matches('\n').count()
+}
+
+// Unified diff between two versions of a file, with "\r\n" shown as plain line breaks
+pub fn unified_diff(path: &str, before: &str, after: &str) -> String {
+    let before = before.replace("\r\n", "\n");
+    let after = after.replace("\r\n", "\n");
+    TextDiff::from_lines(&before, &after)
+        .unified_diff()
+        .context_radius(3)
+        .header(&format!("a/{}", path), &format!("b/{}", path))
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_a_unique_match_and_keeps_crlf() {
+        let content = "fn main() {\r\n    println!(\"hi\");\r\n}\r\n";
+        let edit = replace(content, "    println!(\"hi\");\n}", "    println!(\"bye\");\n}", false).unwrap();
+        assert_eq!(edit.content, "fn main() {\r\n    println!(\"bye\");\r\n}\r\n");
+
+        let diff = unified_diff("src/main.rs", content, &edit.content);
+        assert!(diff.starts_with("--- a/src/main.rs\n+++ b/src/main.rs\n@@ -1,3 +1,3 @@\n"));
+        assert!(diff.contains("-    println!(\"hi\");\n+    println!(\"bye\");\n"));
+    }
+
+    #[test]
+    fn refuses_missing_and_ambiguous_matches() {
+        let content = "a = 1\nb = 1\nc = 2\n";
+        assert!(replace(content, "d = 1", "d = 2", false).unwrap_err().contains("not found"));
+        assert!(replace(content, "= 1", "= 3", false).unwrap_err().contains("matches 2 places (lines 1, 2)"));
+
+        let edit = replace(content, "= 1", "= 3", true).unwrap();
+        assert_eq!((edit.content.as_str(), edit.replacements), ("a = 3\nb = 3\nc = 2\n", 2));
+    }
+}
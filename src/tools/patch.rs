@@ -0,0 +1,791 @@
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+use super::edit;
+
+// Context lines that may be dropped from either end of a hunk that does not match otherwise,
+// as `patch` does with its default fuzz factor
+const MAX_FUZZ: usize = 2;
+
+#[derive(Debug, Default)]
+pub struct FilePatch {
+    // None for /dev/null, when the file is created or deleted
+    old_path: Option<String>,
+    new_path: Option<String>,
+    hunks: Vec<Hunk>,
+    // Paths came from `diff --git`, before the ---/+++ lines
+    from_git_header: bool,
+    has_file_headers: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Hunk {
+    old_start: Option<usize>,
+    lines: Vec<HunkLine>,
+    // "\ No newline at end of file" after the old or new side's last line
+    old_missing_newline: bool,
+    new_missing_newline: bool,
+}
+
+#[derive(Debug, Clone)]
+enum HunkLine {
+    Context(String),
+    Remove(String),
+    Add(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Strictness {
+    Exact,
+    IgnoreTrailingWhitespace,
+    IgnoreWhitespace,
+}
+
+// A file split into lines, remembering how each one ended
+struct Lines {
+    lines: Vec<String>,
+    // "\n" or "\r\n" per line, so lines the patch does not touch keep theirs
+    endings: Vec<&'static str>,
+    // For added lines: whatever most of the file uses
+    eol: &'static str,
+    final_newline: bool,
+}
+
+impl Lines {
+    fn parse(content: &str) -> Self {
+        let eol = if edit::uses_crlf(content) { "\r\n" } else { "\n" };
+        let final_newline = content.is_empty() || content.ends_with('\n');
+        let (lines, endings) = content.lines()
+            .zip(content.split_inclusive('\n'))
+            .map(|(line, raw)| (line.to_string(), if raw.ends_with("\r\n") { "\r\n" } else { "\n" }))
+            .unzip();
+        Self { lines, endings, eol, final_newline }
+    }
+
+    // Replaces the old lines of `hunk` at `position` with its new lines. Context lines keep
+    // the ending they had in the file.
+    fn splice(&mut self, position: usize, hunk: &Hunk) {
+        let old_len = hunk.old_lines().len();
+        let mut old_endings = self.endings[position..position + old_len].iter();
+        let mut lines = Vec::new();
+        let mut endings = Vec::new();
+        for line in &hunk.lines {
+            match line {
+                HunkLine::Context(text) => {
+                    lines.push(text.clone());
+                    endings.push(old_endings.next().copied().unwrap_or(self.eol));
+                }
+                HunkLine::Remove(_) => {
+                    old_endings.next();
+                }
+                HunkLine::Add(text) => {
+                    lines.push(text.clone());
+                    endings.push(self.eol);
+                }
+            }
+        }
+        self.lines.splice(position..position + old_len, lines);
+        self.endings.splice(position..position + old_len, endings);
+    }
+
+    fn render(&self) -> String {
+        let mut content = String::new();
+        for (index, (line, ending)) in self.lines.iter().zip(&self.endings).enumerate() {
+            content.push_str(line);
+            if index + 1 < self.lines.len() || self.final_newline {
+                content.push_str(ending);
+            }
+        }
+        content
+    }
+}
+
+impl Hunk {
+    fn old_lines(&self) -> Vec<&str> {
+        self.lines.iter()
+            .filter_map(|line| match line {
+                HunkLine::Context(text) | HunkLine::Remove(text) => Some(text.as_str()),
+                HunkLine::Add(_) => None,
+            })
+            .collect()
+    }
+
+    fn new_lines(&self) -> Vec<String> {
+        self.lines.iter()
+            .filter_map(|line| match line {
+                HunkLine::Context(text) | HunkLine::Add(text) => Some(text.clone()),
+                HunkLine::Remove(_) => None,
+            })
+            .collect()
+    }
+
+    // Versions of the hunk without up to `fuzz` context lines at the end, the start, or both
+    fn fuzzed(&self, fuzz: usize) -> Vec<Hunk> {
+        let leading = self.lines.iter().take_while(|line| matches!(line, HunkLine::Context(_))).count();
+        let trailing = self.lines.iter().rev().take_while(|line| matches!(line, HunkLine::Context(_))).count();
+        let (front, back) = (leading.min(fuzz), trailing.min(fuzz));
+
+        let mut trims = Vec::new();
+        for trim in [(0, back), (front, 0), (front, back)] {
+            if trim != (0, 0) && trim.0 + trim.1 < self.lines.len() && !trims.contains(&trim) {
+                trims.push(trim);
+            }
+        }
+        trims.into_iter()
+            .map(|(front, back)| {
+                let mut hunk = self.clone();
+                hunk.lines = self.lines[front..self.lines.len() - back].to_vec();
+                hunk.old_start = self.old_start.map(|start| start + front);
+                hunk
+            })
+            .collect()
+    }
+}
+
+fn lines_match(file_line: &str, hunk_line: &str, strictness: Strictness) -> bool {
+    match strictness {
+        Strictness::Exact => file_line == hunk_line,
+        Strictness::IgnoreTrailingWhitespace => file_line.trim_end() == hunk_line.trim_end(),
+        Strictness::IgnoreWhitespace => {
+            file_line.split_whitespace().eq(hunk_line.split_whitespace())
+        }
+    }
+}
+
+// Where `old` occurs in `lines` at or after `from`, closest to `hint`
+fn find(lines: &[String], old: &[&str], from: usize, hint: usize, strictness: Strictness) -> Option<usize> {
+    if old.len() > lines.len() {
+        return None;
+    }
+    let last = lines.len() - old.len();
+    if from > last {
+        return None;
+    }
+
+    let hint = hint.clamp(from, last);
+    let matches_at = |start: usize| {
+        lines[start..start + old.len()].iter()
+            .zip(old)
+            .all(|(file_line, hunk_line)| lines_match(file_line, hunk_line, strictness))
+    };
+
+    (0..=(last - from)).find_map(|distance| {
+        [hint.checked_sub(distance), hint.checked_add(distance)]
+            .into_iter()
+            .flatten()
+            .filter(|&start| start >= from && start <= last)
+            .find(|&start| matches_at(start))
+    })
+}
+
+// Applies the hunks to a file in order, returning the new content and one line per hunk
+fn apply_hunks(path: &str, content: &str, hunks: &[Hunk]) -> Result<(String, Vec<String>), Vec<String>> {
+    let mut file = Lines::parse(content);
+    let mut report = Vec::new();
+    let mut failed = false;
+    // Hunks apply after the previous one, shifted by the lines it added or removed
+    let mut from = 0;
+    let mut shift: isize = 0;
+
+    for (number, hunk) in hunks.iter().enumerate() {
+        let number = number + 1;
+        let mut applied = None;
+
+        'search: for fuzz in 0..=MAX_FUZZ {
+            let candidates = if fuzz == 0 { vec![hunk.clone()] } else { hunk.fuzzed(fuzz) };
+            for candidate in candidates {
+                let old = candidate.old_lines();
+                let expected = candidate.old_start
+                    .map(|start| (start.saturating_sub(1) as isize + shift).max(0) as usize)
+                    .unwrap_or(from);
+
+                for strictness in [Strictness::Exact, Strictness::IgnoreTrailingWhitespace, Strictness::IgnoreWhitespace] {
+                    let position = if old.is_empty() {
+                        Some(expected.clamp(from, file.lines.len()))
+                    } else {
+                        find(&file.lines, &old, from, expected, strictness)
+                    };
+                    if let Some(position) = position {
+                        applied = Some((candidate, position, expected, fuzz, strictness));
+                        break 'search;
+                    }
+                }
+            }
+        }
+
+        let Some((candidate, position, expected, fuzz, strictness)) = applied else {
+            let first_line = hunk.old_lines().into_iter().find(|line| !line.trim().is_empty()).unwrap_or_default();
+            report.push(format!(
+                "{}: hunk {} FAILED: its lines were not found (the first is {:?})",
+                path, number, first_line
+            ));
+            failed = true;
+            continue;
+        };
+
+        let old_len = candidate.old_lines().len();
+        let new_lines = candidate.new_lines();
+        let reaches_end = position + old_len == file.lines.len();
+        let added = new_lines.len() as isize - old_len as isize;
+        file.splice(position, &candidate);
+        if reaches_end && (hunk.old_missing_newline || hunk.new_missing_newline) {
+            file.final_newline = !hunk.new_missing_newline;
+        }
+
+        let mut notes = Vec::new();
+        if position != expected && hunk.old_start.is_some() {
+            notes.push(format!("offset {:+} lines", position as isize - expected as isize));
+        }
+        if fuzz > 0 {
+            notes.push(format!("fuzz {}", fuzz));
+        }
+        match strictness {
+            Strictness::Exact => {}
+            Strictness::IgnoreTrailingWhitespace => notes.push("ignoring trailing whitespace".to_string()),
+            Strictness::IgnoreWhitespace => notes.push("ignoring whitespace".to_string()),
+        }
+        report.push(format!(
+            "{}: hunk {} applied at line {}{}",
+            path,
+            number,
+            position + 1,
+            if notes.is_empty() { String::new() } else { format!(" ({})", notes.join(", ")) }
+        ));
+
+        from = position + new_lines.len();
+        shift += added + (position as isize - expected as isize);
+    }
+
+    if failed {
+        Err(report)
+    } else {
+        Ok((file.render(), report))
+    }
+}
+
+// `--- a/src/main.rs\t2024-01-01 ...` -> src/main.rs
+fn header_path(raw: &str) -> Option<String> {
+    let path = raw.split('\t').next().unwrap_or_default().trim();
+    let path = path.trim_matches('"');
+    if path == "/dev/null" || path.is_empty() {
+        None
+    } else {
+        Some(path.to_string())
+    }
+}
+
+// Drops git's a/ and b/ prefixes, but only when both sides use them
+fn strip_prefixes(old: Option<String>, new: Option<String>) -> (Option<String>, Option<String>) {
+    let prefixed = old.as_deref().is_none_or(|path| path.starts_with("a/"))
+        && new.as_deref().is_none_or(|path| path.starts_with("b/"))
+        && (old.is_some() || new.is_some());
+    if !prefixed {
+        return (old, new);
+    }
+    (
+        old.map(|path| path[2..].to_string()),
+        new.map(|path| path[2..].to_string()),
+    )
+}
+
+// `@@ -12,5 +12,7 @@ fn main()` -> Some(12). Models sometimes leave out the numbers.
+fn hunk_start(header: &str) -> Option<usize> {
+    let old = header.trim_start_matches('@').split_whitespace().next()?.strip_prefix('-')?;
+    old.split(',').next()?.parse().ok()
+}
+
+// `@@ -12,5 +12,7 @@` -> Some((5, 7)). A range without a count has one line.
+fn hunk_counts(header: &str) -> Option<(usize, usize)> {
+    let count = |range: &str, sign: char| {
+        let range = range.strip_prefix(sign)?;
+        match range.split_once(',') {
+            Some((_, count)) => count.parse().ok(),
+            None => range.parse::<usize>().ok().map(|_| 1),
+        }
+    };
+    let mut ranges = header.trim_start_matches('@').split_whitespace();
+    Some((count(ranges.next()?, '-')?, count(ranges.next()?, '+')?))
+}
+
+fn starts_file_headers(lines: &[&str], index: usize) -> bool {
+    lines[index].starts_with("--- ") && lines.get(index + 1).is_some_and(|next| next.starts_with("+++ "))
+}
+
+// Parses a unified diff with one or more files. Lines that are not part of a file or hunk,
+// like `index` lines or prose around the diff, are skipped.
+pub fn parse(patch: &str) -> Result<Vec<FilePatch>, String> {
+    let lines: Vec<&str> = patch.lines().collect();
+    let mut files: Vec<FilePatch> = Vec::new();
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i];
+
+        if let Some(paths) = line.strip_prefix("diff --git ") {
+            let (old, new) = match paths.split_once(" b/") {
+                Some((old, new)) => (Some(old.to_string()), Some(format!("b/{}", new))),
+                None => (None, None),
+            };
+            let (old_path, new_path) = strip_prefixes(old, new);
+            files.push(FilePatch { old_path, new_path, from_git_header: true, ..Default::default() });
+            i += 1;
+            continue;
+        }
+
+        if starts_file_headers(&lines, i) {
+            let (old_path, new_path) = strip_prefixes(
+                header_path(&line[4..]),
+                header_path(&lines[i + 1][4..]),
+            );
+            match files.last_mut() {
+                Some(file) if file.from_git_header && !file.has_file_headers && file.hunks.is_empty() => {
+                    file.old_path = old_path;
+                    file.new_path = new_path;
+                    file.has_file_headers = true;
+                }
+                _ => files.push(FilePatch { old_path, new_path, has_file_headers: true, ..Default::default() }),
+            }
+            i += 2;
+            continue;
+        }
+
+        let Some(file) = files.last_mut() else {
+            i += 1;
+            continue;
+        };
+
+        if let Some(path) = line.strip_prefix("rename from ") {
+            file.old_path = Some(path.to_string());
+        } else if let Some(path) = line.strip_prefix("rename to ") {
+            file.new_path = Some(path.to_string());
+        } else if line.starts_with("new file mode") {
+            file.old_path = None;
+        } else if line.starts_with("deleted file mode") {
+            file.new_path = None;
+        } else if line.starts_with("@@") {
+            let mut hunk = Hunk {
+                old_start: hunk_start(line),
+                lines: Vec::new(),
+                old_missing_newline: false,
+                new_missing_newline: false,
+            };
+            // Lines left on the old and new side. With them the hunk may remove a line like
+            // "-- x" and add "++ y", which would otherwise look like the next file's headers.
+            let mut remaining = hunk_counts(line);
+            // Blank lines are usually context lines that lost their leading space, unless
+            // they end the hunk
+            let mut trailing_blank = 0;
+            i += 1;
+            while i < lines.len() {
+                let body = lines[i];
+                let ended = match remaining {
+                    Some((old, new)) => old == 0 && new == 0 && !body.starts_with('\\'),
+                    None => body.starts_with("@@") || body.starts_with("diff --git ") || starts_file_headers(&lines, i),
+                };
+                if ended {
+                    break;
+                }
+                let parsed = match body.chars().next() {
+                    None => Some(HunkLine::Context(String::new())),
+                    Some(' ') => Some(HunkLine::Context(body[1..].to_string())),
+                    Some('-') => Some(HunkLine::Remove(body[1..].to_string())),
+                    Some('+') => Some(HunkLine::Add(body[1..].to_string())),
+                    Some('\\') => {
+                        match hunk.lines.last() {
+                            Some(HunkLine::Remove(_)) => hunk.old_missing_newline = true,
+                            Some(HunkLine::Add(_)) => hunk.new_missing_newline = true,
+                            _ => {
+                                hunk.old_missing_newline = true;
+                                hunk.new_missing_newline = true;
+                            }
+                        }
+                        i += 1;
+                        continue;
+                    }
+                    Some(_) => None,
+                };
+                let Some(parsed) = parsed else { break };
+                match (&mut remaining, &parsed) {
+                    (Some((old, new)), HunkLine::Context(_)) => {
+                        *old = old.saturating_sub(1);
+                        *new = new.saturating_sub(1);
+                    }
+                    (Some((old, _)), HunkLine::Remove(_)) => *old = old.saturating_sub(1),
+                    (Some((_, new)), HunkLine::Add(_)) => *new = new.saturating_sub(1),
+                    (None, _) => trailing_blank = if body.is_empty() { trailing_blank + 1 } else { 0 },
+                }
+                hunk.lines.push(parsed);
+                i += 1;
+            }
+            hunk.lines.truncate(hunk.lines.len() - trailing_blank);
+            if hunk.lines.iter().any(|line| !matches!(line, HunkLine::Context(_))) {
+                file.hunks.push(hunk);
+            }
+            continue;
+        }
+        i += 1;
+    }
+
+    files.retain(|file| file.old_path.is_some() || file.new_path.is_some());
+    if files.is_empty() {
+        return Err("No file changes found. The patch must be a unified diff with ---/+++ file headers and @@ hunks.".to_string());
+    }
+    Ok(files)
+}
+
+// What the patch does to the file system once every hunk has applied
+#[derive(Debug, Default)]
+pub struct Plan {
+    // Final content per file, or None when the file is deleted, in the order first touched
+    changes: Vec<(PathBuf, Option<String>)>,
+    pub report: Vec<String>,
+}
+
+impl Plan {
+    fn stage(&mut self, path: &Path, content: Option<String>) {
+        match self.changes.iter_mut().find(|(staged, _)| staged == path) {
+            Some((_, staged)) => *staged = content,
+            None => self.changes.push((path.to_path_buf(), content)),
+        }
+    }
+
+    fn staged(&self, path: &Path) -> Option<&Option<String>> {
+        self.changes.iter().find(|(staged, _)| staged == path).map(|(_, content)| content)
+    }
+}
+
+// Where a path from the patch points below `root`. Paths that leave it, like "../../etc/x",
+// an absolute path elsewhere or a symlink to another directory, are refused rather than left
+// to the approval prompt.
+fn resolve(root: &Path, path: &str) -> Result<PathBuf, String> {
+    let relative = Path::new(path).strip_prefix(root).unwrap_or(Path::new(path));
+    let mut resolved = PathBuf::new();
+    for component in relative.components() {
+        match component {
+            Component::Normal(part) => resolved.push(part),
+            Component::CurDir => {}
+            Component::ParentDir if resolved.pop() => {}
+            _ => return Err("the path is outside the working directory".to_string()),
+        }
+    }
+    if resolved.as_os_str().is_empty() {
+        return Err("the path is not a file".to_string());
+    }
+
+    // The part of the path that exists is checked where it really is
+    let full = root.join(resolved);
+    let existing = full.ancestors().find(|ancestor| ancestor.symlink_metadata().is_ok()).unwrap_or(root);
+    let real = existing.canonicalize().map_err(|e| format!("cannot resolve the path: {}", e))?;
+    let real_root = root.canonicalize().map_err(|e| format!("cannot resolve the working directory: {}", e))?;
+    if !real.starts_with(real_root) {
+        return Err("the path is outside the working directory".to_string());
+    }
+    Ok(full)
+}
+
+async fn current(plan: &Plan, path: &Path) -> Result<Option<String>, String> {
+    if let Some(staged) = plan.staged(path) {
+        return Ok(staged.clone());
+    }
+    match tokio::fs::read(path).await {
+        Ok(bytes) => String::from_utf8(bytes)
+            .map(Some)
+            .map_err(|_| "the file is not UTF-8 text".to_string()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("cannot read the file: {}", e)),
+    }
+}
+
+// Works out every file's new content without touching the disk. Paths are taken relative to
+// `root`. Fails with the full report when any hunk does not apply.
+pub async fn plan(files: &[FilePatch], root: &Path) -> Result<Plan, Vec<String>> {
+    let mut plan = Plan::default();
+    let mut failed = false;
+
+    for file in files {
+        let (old_path, new_path) = (file.old_path.as_deref(), file.new_path.as_deref());
+        let source = old_path.or(new_path).unwrap_or_default();
+
+        let resolved = |path: Option<&str>| {
+            path.map(|path| resolve(root, path).map_err(|e| format!("{}: FAILED: {}", path, e))).transpose()
+        };
+        let (old_full, new_full) = match (resolved(old_path), resolved(new_path)) {
+            (Ok(old_full), Ok(new_full)) => (old_full, new_full),
+            (old, new) => {
+                plan.report.extend(old.err().into_iter().chain(new.err()));
+                failed = true;
+                continue;
+            }
+        };
+
+        let existing = match (old_path, &old_full) {
+            (Some(path), Some(full)) => match current(&plan, full).await {
+                Ok(Some(content)) => Some(content),
+                Ok(None) => {
+                    plan.report.push(format!("{}: FAILED: the file does not exist", path));
+                    failed = true;
+                    continue;
+                }
+                Err(e) => {
+                    plan.report.push(format!("{}: FAILED: {}", path, e));
+                    failed = true;
+                    continue;
+                }
+            },
+            _ => None,
+        };
+
+        if let (Some(path), Some(full)) = (new_path, &new_full) {
+            if old_full.as_ref() != Some(full) && matches!(current(&plan, full).await, Ok(Some(_))) {
+                plan.report.push(format!("{}: FAILED: the file already exists", path));
+                failed = true;
+                continue;
+            }
+        }
+
+        let (Some(new_path), Some(new_full)) = (new_path, new_full) else {
+            // The removed lines still have to match, or the file is not the one the model saw
+            if let Err(hunks) = apply_hunks(source, existing.as_deref().unwrap_or_default(), &file.hunks) {
+                plan.report.extend(hunks);
+                failed = true;
+                continue;
+            }
+            if let Some(full) = &old_full {
+                plan.stage(full, None);
+            }
+            plan.report.push(format!("Deleted {}", source));
+            continue;
+        };
+
+        let content = existing.unwrap_or_default();
+        let patched = if file.hunks.is_empty() {
+            content
+        } else {
+            match apply_hunks(source, &content, &file.hunks) {
+                Ok((patched, hunks)) => {
+                    plan.report.extend(hunks);
+                    patched
+                }
+                Err(hunks) => {
+                    plan.report.extend(hunks);
+                    failed = true;
+                    continue;
+                }
+            }
+        };
+
+        match (old_path, &old_full) {
+            (Some(old_path), Some(old_full)) if *old_full != new_full => {
+                plan.stage(old_full, None);
+                plan.report.push(format!("Renamed {} to {}", old_path, new_path));
+            }
+            (Some(_), _) => plan.report.push(format!("Modified {}", new_path)),
+            (None, _) => plan.report.push(format!("Created {}", new_path)),
+        }
+        plan.stage(&new_full, Some(patched));
+    }
+
+    if failed {
+        plan.report.push("No files were changed.".to_string());
+        Err(plan.report)
+    } else {
+        Ok(plan)
+    }
+}
+
+// Writes the planned changes. New content goes to a temporary file next to each target
+// first, so a failure part way through can put back what was already changed.
+pub async fn write(plan: &Plan) -> Result<(), String> {
+    let mut originals: HashMap<&Path, Option<Vec<u8>>> = HashMap::new();
+    for (path, _) in &plan.changes {
+        originals.insert(path, tokio::fs::read(path).await.ok());
+    }
+
+    let mut done: Vec<&Path> = Vec::new();
+    for (path, content) in &plan.changes {
+        let result = match content {
+            Some(content) => write_file(path, content).await,
+            None => tokio::fs::remove_file(path).await,
+        };
+        if let Err(e) = result {
+            for restored in done {
+                let _ = match &originals[restored] {
+                    Some(original) => tokio::fs::write(restored, original).await,
+                    None => tokio::fs::remove_file(restored).await,
+                };
+            }
+            return Err(format!("Cannot write {}: {}. No files were changed.", path.display(), e));
+        }
+        done.push(path);
+    }
+    Ok(())
+}
+
+async fn write_file(path: &Path, content: &str) -> std::io::Result<()> {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".crush-patch");
+    tokio::fs::write(&temporary, content).await?;
+    tokio::fs::rename(&temporary, path).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hunks(patch: &str) -> Vec<Hunk> {
+        parse(patch).unwrap().remove(0).hunks
+    }
+
+    #[test]
+    fn parses_creates_deletes_and_renames() {
+        let patch = "\
+diff --git a/old.rs b/new.rs
+similarity index 90%
+rename from old.rs
+rename to new.rs
+--- a/old.rs
++++ b/new.rs
+@@ -1 +1 @@
+-fn old() {}
++fn new() {}
+--- /dev/null
++++ b/notes.md
+@@ -0,0 +1,2 @@
++# Notes
++
+diff --git a/gone.txt b/gone.txt
+deleted file mode 100644
+";
+        let files = parse(patch).unwrap();
+        let paths: Vec<_> = files.iter().map(|f| (f.old_path.as_deref(), f.new_path.as_deref(), f.hunks.len())).collect();
+        assert_eq!(paths, [
+            (Some("old.rs"), Some("new.rs"), 1),
+            (None, Some("notes.md"), 1),
+            (Some("gone.txt"), None, 0),
+        ]);
+        // The blank line after "+# Notes" is part of the file, not the end of the hunk
+        assert_eq!(files[1].hunks[0].new_lines(), ["# Notes", ""]);
+    }
+
+    #[test]
+    fn uses_hunk_counts_to_find_the_end_of_a_hunk() {
+        let patch = "\
+--- a/query.sql
++++ b/query.sql
+@@ -1,2 +1,2 @@
+--- old comment
++++ new comment
+ SELECT 1;
+@@ -5 +5 @@
+-SELECT 2;
++SELECT 3;
+";
+        let hunks = hunks(patch);
+        assert_eq!(hunks.len(), 2);
+        assert_eq!(hunks[0].old_lines(), ["-- old comment", "SELECT 1;"]);
+        assert_eq!(hunks[0].new_lines(), ["++ new comment", "SELECT 1;"]);
+
+        let (patched, _) = apply_hunks("query.sql", "-- old comment\nSELECT 1;\n", &hunks[..1]).unwrap();
+        assert_eq!(patched, "++ new comment\nSELECT 1;\n");
+
+        // Without counts the same lines read as the headers of another file
+        let files = parse(&patch.replace("@@ -1,2 +1,2 @@", "@@")).unwrap();
+        assert_eq!(files.len(), 2);
+    }
+
+    #[test]
+    fn applies_hunks_with_offsets_and_fuzz() {
+        let content = "// header\r\nfn a() {\r\n    1\r\n}\r\n\r\nfn b() {\r\n    2\r\n}\r\n";
+        let patch = "\
+--- a/lib.rs
++++ b/lib.rs
+@@ -1,3 +1,3 @@
+ fn a() {
+-    1
++    10
+ }
+@@ -5,4 +5,4 @@
+ fn b() {
+-    2
++    20
+ }
+ // a context line the file does not have
+";
+        let (patched, report) = apply_hunks("lib.rs", content, &hunks(patch)).unwrap();
+        assert_eq!(patched, "// header\r\nfn a() {\r\n    10\r\n}\r\n\r\nfn b() {\r\n    20\r\n}\r\n");
+        assert_eq!(report, [
+            "lib.rs: hunk 1 applied at line 2 (offset +1 lines)",
+            "lib.rs: hunk 2 applied at line 6 (fuzz 1)",
+        ]);
+    }
+
+    #[test]
+    fn keeps_each_line_ending() {
+        let content = "one\r\ntwo\nthree\r\nfour\n";
+        let patch = "--- a/mixed.txt\n+++ b/mixed.txt\n@@ -1,4 +1,4 @@\n one\n-two\n+2\n three\n four\n";
+        let (patched, _) = apply_hunks("mixed.txt", content, &hunks(patch)).unwrap();
+        // The added line takes the file's usual ending; the others keep theirs
+        assert_eq!(patched, "one\r\n2\r\nthree\r\nfour\n");
+    }
+
+    #[test]
+    fn reports_hunks_that_do_not_apply() {
+        let patch = "--- a/lib.rs\n+++ b/lib.rs\n@@ -1 +1 @@\n-fn missing() {}\n+fn found() {}\n";
+        let report = apply_hunks("lib.rs", "fn a() {}\n", &hunks(patch)).unwrap_err();
+        assert_eq!(report, ["lib.rs: hunk 1 FAILED: its lines were not found (the first is \"fn missing() {}\")"]);
+    }
+
+    #[tokio::test]
+    async fn changes_no_file_unless_every_hunk_applies() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (dir.path().join("a.txt"), dir.path().join("b.txt"));
+        std::fs::write(&a, "one\ntwo\n").unwrap();
+        std::fs::write(&b, "three\n").unwrap();
+        let patch = |b_line: &str| format!(
+            "--- {a}\n+++ {a}\n@@ -1,2 +1,2 @@\n one\n-two\n+2\n--- {b}\n+++ /dev/null\n@@ -1 +0,0 @@\n-{b_line}\n",
+            a = a.display(),
+            b = b.display(),
+        );
+
+        let report = plan(&parse(&patch("four")).unwrap(), dir.path()).await.unwrap_err();
+        assert_eq!(report.last().unwrap(), "No files were changed.");
+        assert_eq!(std::fs::read_to_string(&a).unwrap(), "one\ntwo\n");
+
+        let planned = plan(&parse(&patch("three")).unwrap(), dir.path()).await.unwrap();
+        write(&planned).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&a).unwrap(), "one\n2\n");
+        assert!(!b.exists());
+    }
+
+    #[tokio::test]
+    async fn refuses_paths_outside_the_working_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("project");
+        std::fs::create_dir(&root).unwrap();
+        let create = |path: &str| format!("--- /dev/null\n+++ {}\n@@ -0,0 +1 @@\n+owned\n", path);
+
+        for path in ["../outside.txt", "src/../../outside.txt", "/etc/outside.txt"] {
+            let report = plan(&parse(&create(path)).unwrap(), &root).await.unwrap_err();
+            assert_eq!(report, [
+                format!("{}: FAILED: the path is outside the working directory", path),
+                "No files were changed.".to_string(),
+            ]);
+        }
+
+        #[cfg(unix)]
+        {
+            std::fs::create_dir(dir.path().join("elsewhere")).unwrap();
+            std::os::unix::fs::symlink(dir.path().join("elsewhere"), root.join("linked")).unwrap();
+            let report = plan(&parse(&create("linked/outside.txt")).unwrap(), &root).await.unwrap_err();
+            assert_eq!(report[0], "linked/outside.txt: FAILED: the path is outside the working directory");
+        }
+
+        let planned = plan(&parse(&create("b/src/../notes.txt")).unwrap(), &root).await.unwrap();
+        write(&planned).await.unwrap();
+        assert_eq!(std::fs::read_to_string(root.join("notes.txt")).unwrap(), "owned\n");
+        assert!(!dir.path().join("outside.txt").exists());
+    }
+}
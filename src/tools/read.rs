@@ -0,0 +1,218 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+// Lines shown when the model does not ask for a range
+pub const DEFAULT_LIMIT: usize = 2000;
+// Output is cut after this many bytes even within the requested lines
+const MAX_OUTPUT_BYTES: usize = 100 * 1024;
+// Longer lines, e.g. in minified files, are cut
+const MAX_LINE_CHARS: usize = 2000;
+// Bytes kept of a line while reading, enough for MAX_LINE_CHARS characters of any width
+const MAX_LINE_BYTES: usize = MAX_LINE_CHARS * 4 + 4;
+// Looked at to tell binary files from text
+const SNIFF_BYTES: usize = 8192;
+
+// Recognised by their first bytes, for the summary of a binary file
+const SIGNATURES: &[(&[u8], &str)] = &[
+    (b"\x89PNG", "PNG image"),
+    (b"\xFF\xD8\xFF", "JPEG image"),
+    (b"GIF8", "GIF image"),
+    (b"%PDF", "PDF document"),
+    (b"PK\x03\x04", "zip archive"),
+    (b"\x1F\x8B", "gzip archive"),
+    (b"\x7FELF", "ELF executable"),
+    (b"MZ", "Windows executable"),
+    (b"\0asm", "WebAssembly module"),
+    (b"SQLite format 3", "SQLite database"),
+];
+
+// Text files can contain control characters, but not NUL bytes. Two-byte signatures are too
+// likely at the start of a text file to count on their own.
+fn is_binary(bytes: &[u8]) -> bool {
+    bytes.iter().take(SNIFF_BYTES).any(|&byte| byte == 0)
+        || SIGNATURES.iter().any(|(magic, _)| bytes.starts_with(magic) && magic.len() > 2)
+}
+
+fn size(bytes: u64) -> String {
+    match bytes {
+        0..=1023 => format!("{} bytes", bytes),
+        1024..=1_048_575 => format!("{:.1} KB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+// Reads up to the next "\n" into `line`, keeping at most `keep` bytes so one huge line cannot
+// fill the memory. Returns None at the end of the input, otherwise whether bytes were dropped.
+fn read_line(reader: &mut impl BufRead, line: &mut Vec<u8>, keep: usize) -> std::io::Result<Option<bool>> {
+    line.clear();
+    let mut read_any = false;
+    let mut cut = false;
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            return Ok(read_any.then_some(cut));
+        }
+        read_any = true;
+        let (end, done) = match buffer.iter().position(|&byte| byte == b'\n') {
+            Some(newline) => (newline + 1, true),
+            None => (buffer.len(), false),
+        };
+        let room = keep.saturating_sub(line.len());
+        let content = &buffer[..end];
+        let content = content.strip_suffix(b"\n").unwrap_or(content);
+        line.extend_from_slice(&content[..content.len().min(room)]);
+        cut |= content.len() > room;
+        reader.consume(end);
+        if done {
+            if !cut && line.ends_with(b"\r") {
+                line.pop();
+            }
+            return Ok(Some(cut));
+        }
+    }
+}
+
+// Opens the file and renders the requested lines, without reading past them
+pub fn read_file(path: &str, offset: usize, limit: usize) -> Result<String, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let size = file.metadata().map_err(|e| e.to_string())?.len();
+    render(path, BufReader::with_capacity(SNIFF_BYTES, file), size, offset, limit)
+}
+
+// The lines from `offset` (1-based) on, numbered like `cat -n`, with a note when the output
+// stops before the end of the file. Binary files get a one-line summary instead.
+pub fn render(path: &str, mut reader: impl BufRead, file_size: u64, offset: usize, limit: usize) -> Result<String, String> {
+    if offset == 0 {
+        return Err("offset starts at 1 for the first line".to_string());
+    }
+    if limit == 0 {
+        return Err("limit must be at least 1".to_string());
+    }
+
+    let start = reader.fill_buf().map_err(|e| e.to_string())?;
+    if is_binary(start) {
+        let kind = SIGNATURES.iter()
+            .find(|(magic, _)| start.starts_with(magic))
+            .map(|(_, kind)| *kind)
+            .unwrap_or("binary file");
+        return Ok(format!("{} is a {} ({}); its contents are not shown", path, kind, size(file_size)));
+    }
+    if start.starts_with(b"\xEF\xBB\xBF") {
+        reader.consume(3);
+    }
+
+    let mut line = Vec::new();
+    let mut number = 0;
+    let mut output = String::new();
+    let mut lossy = false;
+    let mut stopped_early = false;
+    loop {
+        // Lines before the offset are only counted
+        let keep = if number + 1 < offset { 0 } else { MAX_LINE_BYTES };
+        let Some(cut) = read_line(&mut reader, &mut line, keep).map_err(|e| e.to_string())? else { break };
+        number += 1;
+        if number < offset {
+            continue;
+        }
+        if number - offset == limit {
+            stopped_early = true;
+            number -= 1;
+            break;
+        }
+
+        // Bytes dropped mid-character at a cut are not the file's fault
+        lossy |= std::str::from_utf8(&line).is_err_and(|e| !cut || e.error_len().is_some());
+        let text = String::from_utf8_lossy(&line);
+        let text = match text.char_indices().nth(MAX_LINE_CHARS) {
+            Some((end, _)) => format!("{}... (line cut)", &text[..end]),
+            None if cut => format!("{}... (line cut)", text),
+            None => text.into_owned(),
+        };
+        let numbered = format!("{:>6}\t{}\n", number, text);
+        if output.len() + numbered.len() > MAX_OUTPUT_BYTES && !output.is_empty() {
+            stopped_early = true;
+            number -= 1;
+            break;
+        }
+        output.push_str(&numbered);
+    }
+
+    if number == 0 {
+        return Ok(format!("{} is empty", path));
+    }
+    if number < offset {
+        return Err(format!("offset {} is beyond the end of the file, which has {} lines", offset, number));
+    }
+
+    if lossy {
+        output.push_str("(The file is not valid UTF-8; undecodable bytes are shown as \u{FFFD}.)\n");
+    }
+    if stopped_early {
+        output.push_str(&format!(
+            "(Showing lines {}-{}; the file continues and is {} in total. Use offset and limit to read other lines.)\n",
+            offset,
+            number,
+            size(file_size)
+        ));
+    } else if offset > 1 {
+        output.push_str(&format!(
+            "(Showing lines {}-{} of {}. Use offset and limit to read other lines.)\n",
+            offset,
+            number,
+            number
+        ));
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(content: &[u8], offset: usize, limit: usize) -> Result<String, String> {
+        render("a.txt", content, content.len() as u64, offset, limit)
+    }
+
+    #[test]
+    fn numbers_the_requested_lines() {
+        let content = b"one\r\ntwo\nthree\nfour\n";
+        assert_eq!(read(content, 1, DEFAULT_LIMIT).unwrap(), "     1\tone\n     2\ttwo\n     3\tthree\n     4\tfour\n");
+        assert_eq!(
+            read(content, 2, 2).unwrap(),
+            "     2\ttwo\n     3\tthree\n(Showing lines 2-3; the file continues and is 20 bytes in total. Use offset and limit to read other lines.)\n"
+        );
+        assert_eq!(
+            read(content, 3, 10).unwrap(),
+            "     3\tthree\n     4\tfour\n(Showing lines 3-4 of 4. Use offset and limit to read other lines.)\n"
+        );
+        assert!(read(content, 5, 10).unwrap_err().contains("which has 4 lines"));
+        assert!(read(content, 1, 0).is_err());
+        assert_eq!(read(b"", 1, 10).unwrap(), "a.txt is empty");
+    }
+
+    #[test]
+    fn summarises_binary_files_and_decodes_the_rest_lossily() {
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+        assert_eq!(
+            render("logo.png", &png[..], 16, 1, DEFAULT_LIMIT).unwrap(),
+            "logo.png is a PNG image (16 bytes); its contents are not shown"
+        );
+        assert_eq!(
+            read(b"caf\xE9\n", 1, DEFAULT_LIMIT).unwrap(),
+            "     1\tcaf\u{FFFD}\n(The file is not valid UTF-8; undecodable bytes are shown as \u{FFFD}.)\n"
+        );
+    }
+
+    #[test]
+    fn keeps_long_lines_and_large_files_bounded() {
+        let long_line = "é".repeat(MAX_LINE_BYTES);
+        let output = read(format!("{}\nnext\n", long_line).as_bytes(), 1, DEFAULT_LIMIT).unwrap();
+        assert!(output.starts_with(&format!("     1\t{}... (line cut)\n", "é".repeat(MAX_LINE_CHARS))));
+        assert!(output.ends_with("     2\tnext\n"));
+
+        let content = "0123456789".repeat(10).repeat(2000).replace("9", "9\n");
+        let output = read(content.as_bytes(), 1, DEFAULT_LIMIT).unwrap();
+        assert!(output.len() < MAX_OUTPUT_BYTES + 200);
+        assert!(output.ends_with("the file continues and is 214.8 KB in total. Use offset and limit to read other lines.)\n"));
+    }
+}
@@ -0,0 +1,314 @@
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::{Walk, WalkBuilder};
+use regex::RegexBuilder;
+use std::path::Path;
+use std::time::SystemTime;
+
+// Lines longer than this are cut in results, e.g. minified files
+const MAX_LINE_CHARS: usize = 300;
+// Larger files are skipped rather than read into memory; they are rarely source code
+const MAX_FILE_BYTES: u64 = 5 * 1024 * 1024;
+
+pub struct GrepOptions {
+    pub pattern: String,
+    pub path: String,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub context: usize,
+    pub case_sensitive: bool,
+    pub max_results: usize,
+}
+
+// Files under `root` in path order, skipping hidden files and anything .gitignore, .ignore or
+// the global git excludes leave out, in or outside of a git repository
+fn walk(root: &Path) -> Walk {
+    WalkBuilder::new(root)
+        .require_git(false)
+        .sort_by_file_path(|a, b| a.cmp(b))
+        .build()
+}
+
+fn glob_set(patterns: &[String]) -> Result<Option<GlobSet>, String> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern).map_err(|e| format!("Invalid glob '{}': {}", pattern, e))?);
+    }
+    builder.build().map(Some).map_err(|e| e.to_string())
+}
+
+// How a path under the search root is shown: relative to the working directory, without "./"
+fn display_path(path: &Path) -> String {
+    let path = path.strip_prefix(".").unwrap_or(path);
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let path = path.strip_prefix(&cwd).unwrap_or(path);
+    path.to_string_lossy().replace('\\', "/")
+}
+
+// "1 match", "2 matches"
+fn count(n: usize, one: &str, many: &str) -> String {
+    format!("{} {}", n, if n == 1 { one } else { many })
+}
+
+fn clip(line: &str) -> String {
+    match line.char_indices().nth(MAX_LINE_CHARS) {
+        Some((end, _)) => format!("{}...", &line[..end]),
+        None => line.to_string(),
+    }
+}
+
+// Searches the files under `options.path` and returns `path:line:text` for matches and
+// `path-line-text` for context lines, with "--" between groups that are not adjacent
+pub fn grep(options: &GrepOptions) -> Result<String, String> {
+    let regex = RegexBuilder::new(&options.pattern)
+        .case_insensitive(!options.case_sensitive)
+        .build()
+        .map_err(|e| format!("Invalid regex: {}", e))?;
+    let include = glob_set(&options.include)?;
+    let exclude = glob_set(&options.exclude)?;
+    let root = Path::new(&options.path);
+    if !root.exists() {
+        return Err(format!("{} does not exist", options.path));
+    }
+    if options.max_results == 0 {
+        return Err("max_results must be at least 1".to_string());
+    }
+
+    let mut output = Vec::new();
+    let mut matches = 0;
+    let mut truncated = false;
+    let mut skipped = 0;
+
+    for entry in walk(root).flatten() {
+        if !entry.file_type().is_some_and(|kind| kind.is_file()) {
+            continue;
+        }
+        // Globs match the path below the search root, so "*.rs" also finds src/main.rs
+        let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+        let relative = if relative.as_os_str().is_empty() { entry.path() } else { relative };
+        if include.as_ref().is_some_and(|set| !set.is_match(relative))
+            || exclude.as_ref().is_some_and(|set| set.is_match(relative))
+        {
+            continue;
+        }
+
+        if entry.metadata().is_ok_and(|metadata| metadata.len() > MAX_FILE_BYTES) {
+            skipped += 1;
+            continue;
+        }
+        let Ok(bytes) = std::fs::read(entry.path()) else { continue };
+        if bytes.iter().take(8192).any(|&byte| byte == 0) {
+            continue;
+        }
+        let content = String::from_utf8_lossy(&bytes);
+        let lines: Vec<&str> = content.lines().collect();
+
+        let mut hits = Vec::new();
+        for (index, line) in lines.iter().enumerate() {
+            if regex.is_match(line) {
+                if matches == options.max_results {
+                    truncated = true;
+                    break;
+                }
+                matches += 1;
+                hits.push(index);
+            }
+        }
+        if hits.is_empty() {
+            // The cap can be reached on the first match in a file, with nothing left to print
+            if truncated {
+                break;
+            }
+            continue;
+        }
+
+        let path = display_path(entry.path());
+        let mut printed_until: Option<usize> = None;
+        for &hit in &hits {
+            let start = hit.saturating_sub(options.context);
+            let end = (hit + options.context).min(lines.len() - 1);
+            let adjacent = printed_until.is_some_and(|last| start <= last + 1);
+            if options.context > 0 && !adjacent && !output.is_empty() {
+                output.push("--".to_string());
+            }
+            let first = printed_until.map_or(start, |last| start.max(last + 1));
+            for (index, line) in lines.iter().enumerate().take(end + 1).skip(first) {
+                let separator = if hits.binary_search(&index).is_ok() { ':' } else { '-' };
+                output.push(format!("{}{}{}{}{}", path, separator, index + 1, separator, clip(line)));
+            }
+            printed_until = Some(end);
+        }
+
+        if truncated {
+            break;
+        }
+    }
+
+    if output.is_empty() {
+        output.push("No matches found".to_string());
+    }
+    if truncated {
+        output.push(format!(
+            "(Stopped after {}. Narrow the pattern or path, or raise max_results.)",
+            count(options.max_results, "match", "matches")
+        ));
+    }
+    if skipped > 0 {
+        output.push(format!(
+            "(Skipped {} larger than {} MB.)",
+            count(skipped, "file", "files"),
+            MAX_FILE_BYTES / (1024 * 1024)
+        ));
+    }
+    Ok(output.join("\n"))
+}
+
+// Files under `path` whose path below it matches `pattern`, most recently modified first.
+// `*` stays within a directory; `**` crosses any number of them.
+pub fn glob(pattern: &str, path: &str, max_results: usize) -> Result<String, String> {
+    let matcher = GlobBuilder::new(pattern)
+        .literal_separator(true)
+        .build()
+        .map_err(|e| format!("Invalid glob '{}': {}", pattern, e))?
+        .compile_matcher();
+    let root = Path::new(path);
+    if !root.is_dir() {
+        return Err(format!("{} is not a directory", path));
+    }
+    if max_results == 0 {
+        return Err("max_results must be at least 1".to_string());
+    }
+
+    let mut files: Vec<(SystemTime, String)> = walk(root)
+        .flatten()
+        .filter(|entry| entry.file_type().is_some_and(|kind| kind.is_file()))
+        .filter(|entry| matcher.is_match(entry.path().strip_prefix(root).unwrap_or(entry.path())))
+        .map(|entry| {
+            let modified = entry.metadata().ok()
+                .and_then(|metadata| metadata.modified().ok())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            (modified, display_path(entry.path()))
+        })
+        .collect();
+
+    if files.is_empty() {
+        return Ok("No files found".to_string());
+    }
+    // Newest first; the walk order keeps ties in path order
+    files.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+
+    let total = files.len();
+    let mut output: Vec<String> = files.into_iter().take(max_results).map(|(_, path)| path).collect();
+    if total > max_results {
+        output.push(format!(
+            "(Showing the {} most recently modified of {} files. Use a narrower pattern to see the rest.)",
+            max_results, total
+        ));
+    }
+    Ok(output.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(root: &Path, pattern: &str) -> GrepOptions {
+        GrepOptions {
+            pattern: pattern.to_string(),
+            path: root.display().to_string(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            context: 0,
+            case_sensitive: true,
+            max_results: 100,
+        }
+    }
+
+    #[test]
+    fn finds_matches_outside_ignored_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::create_dir_all(root.join("target")).unwrap();
+        std::fs::write(root.join(".gitignore"), "target/\n").unwrap();
+        std::fs::write(root.join("src/main.rs"), "fn main() {\n    run();\n}\n\nfn run() {}\n").unwrap();
+        std::fs::write(root.join("src/notes.md"), "run the tests\n").unwrap();
+        std::fs::write(root.join("target/out.rs"), "fn run() {}\n").unwrap();
+        let path = |name: &str| root.join(name).display().to_string();
+
+        let found = grep(&GrepOptions { include: vec!["*.rs".to_string()], ..options(root, r"\brun\(") }).unwrap();
+        assert_eq!(found, format!("{}:2:    run();\n{}:5:fn run() {{}}", path("src/main.rs"), path("src/main.rs")));
+
+        let found = grep(&GrepOptions { context: 1, case_sensitive: false, ..options(root, "^RUN") }).unwrap();
+        assert_eq!(found, format!("{}:1:run the tests", path("src/notes.md")));
+
+        let found = grep(&GrepOptions { context: 1, max_results: 1, ..options(root, "run") }).unwrap();
+        assert_eq!(found, format!(
+            "{}-1-fn main() {{\n{}:2:    run();\n{}-3-}}\n(Stopped after 1 match. Narrow the pattern or path, or raise max_results.)",
+            path("src/main.rs"), path("src/main.rs"), path("src/main.rs")
+        ));
+
+        assert!(grep(&GrepOptions { max_results: 0, ..options(root, "run") }).is_err());
+    }
+
+    #[test]
+    fn stops_at_max_results_across_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            std::fs::write(root.join(name), "needle\n").unwrap();
+        }
+        // Reached only if the walk goes on past the cap, which would report it as skipped
+        std::fs::File::create(root.join("d.txt")).unwrap().set_len(MAX_FILE_BYTES + 1).unwrap();
+        let path = |name: &str| root.join(name).display().to_string();
+
+        let found = grep(&GrepOptions { max_results: 1, ..options(root, "needle") }).unwrap();
+        assert_eq!(found, format!(
+            "{}:1:needle\n(Stopped after 1 match. Narrow the pattern or path, or raise max_results.)",
+            path("a.txt")
+        ));
+
+        let found = grep(&GrepOptions { max_results: 2, ..options(root, "needle") }).unwrap();
+        assert_eq!(found, format!(
+            "{}:1:needle\n{}:1:needle\n(Stopped after 2 matches. Narrow the pattern or path, or raise max_results.)",
+            path("a.txt"), path("b.txt")
+        ));
+    }
+
+    #[test]
+    fn skips_files_too_large_to_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("small.txt"), "needle\n").unwrap();
+        std::fs::File::create(root.join("huge.txt")).unwrap().set_len(MAX_FILE_BYTES + 1).unwrap();
+
+        let found = grep(&options(root, "needle")).unwrap();
+        assert_eq!(found, format!("{}:1:needle\n(Skipped 1 file larger than 5 MB.)", root.join("small.txt").display()));
+    }
+
+    #[test]
+    fn globs_newest_first_within_the_pattern() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("src/tools")).unwrap();
+        std::fs::write(root.join(".gitignore"), "*.log\n").unwrap();
+        for (name, age) in [("src/main.rs", 30), ("src/tools/edit.rs", 10), ("src/tools/grep.rs", 20), ("build.log", 0)] {
+            let file = std::fs::File::create(root.join(name)).unwrap();
+            file.set_modified(SystemTime::now() - std::time::Duration::from_secs(age)).unwrap();
+        }
+        let root_path = root.display().to_string();
+        let path = |name: &str| root.join(name).display().to_string();
+
+        assert_eq!(glob("src/*.rs", &root_path, 10).unwrap(), path("src/main.rs"));
+        assert_eq!(
+            glob("**/*.rs", &root_path, 2).unwrap(),
+            format!(
+                "{}\n{}\n(Showing the 2 most recently modified of 3 files. Use a narrower pattern to see the rest.)",
+                path("src/tools/edit.rs"), path("src/tools/grep.rs")
+            )
+        );
+        assert_eq!(glob("*.log", &root_path, 10).unwrap(), "No files found");
+    }
+}